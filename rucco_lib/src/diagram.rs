@@ -0,0 +1,260 @@
+//! Diagrams in docs: fenced `dot` (graphviz) and `sequence` code blocks are
+//! drawn as inline svg at generation time, graphs being laid out by layout-rs.
+//! A diagram that cannot be drawn is shown as code instead.
+//!
+//! ```sequence
+//! participant Browser
+//! participant S as Server
+//! Browser -> S: GET /
+//! S --> Browser: 200 OK
+//! ```
+//!
+//! Sequence diagrams are messages between participants, top down: `A -> B:
+//! text`, `-->` for a dashed arrow (a reply), `A -> A: text` for a message to
+//! oneself. Participants are declared by `participant A` (`participant A as
+//! Some Name` to label it) or else by their first message, from left to right.
+//! Mermaid's `->>` arrows, `sequenceDiagram` header and `%%` comments are
+//! accepted too.
+
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+
+use layout::backends::svg::SVGWriter;
+use layout::gv::{DotParser, GraphBuilder};
+use maud::Markup;
+use regex::Regex;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Diagram {
+    Dot,
+    Sequence
+}
+
+impl Diagram {
+    /// The diagram a code block fence names, if any.
+    pub fn from_language(language: &str) -> Option<Diagram> {
+        match language {
+            "dot" | "graphviz" => Some(Diagram::Dot),
+            "sequence" => Some(Diagram::Sequence),
+            _ => None
+        }
+    }
+
+    /// The inline svg of a diagram, or why it could not be drawn.
+    pub fn to_svg(&self, source: &str) -> Result<String, String> {
+        match *self {
+            Diagram::Dot => dot_svg(source),
+            Diagram::Sequence => sequence_svg(source).map(|svg| svg.into_string())
+        }
+    }
+}
+
+/// ----------------------------------------------------------------------------
+/// Graphviz
+
+/// Whether this thread is laying a graph out: layout-rs panics on some graphs
+/// it cannot lay out, which are caught and reported as errors, so a panic hook
+/// can keep them quiet.
+pub fn is_laying_out() -> bool {
+    LAYING_OUT.with(Cell::get)
+}
+
+thread_local! {
+    static LAYING_OUT: Cell<bool> = Cell::new(false);
+}
+
+fn lay_out<F: FnOnce() -> String>(draw: F) -> Result<String, String> {
+    LAYING_OUT.with(|l| l.set(true));
+    let svg = panic::catch_unwind(AssertUnwindSafe(draw));
+    LAYING_OUT.with(|l| l.set(false));
+    svg.map_err(|payload| {
+        let reason = payload.downcast_ref::<&str>().map(|r| r.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown error".to_string());
+        format!("the graph could not be laid out: {}", reason)
+    })
+}
+
+fn dot_svg(source: &str) -> Result<String, String> {
+    let graph = DotParser::new(source).process()
+        .map_err(|e| format!("could not parse the graph: {}", e))?;
+    let svg = lay_out(|| {
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let mut visual_graph = builder.get();
+        let mut writer = SVGWriter::new();
+        visual_graph.do_it(false, false, false, &mut writer);
+        writer.finalize()
+    })?;
+    // inline: no xml declaration, and ids (arrows, markers) unique to the page
+    let svg = &svg[svg.find("<svg").unwrap_or(0)..];
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    let prefix = format!("dot{:x}-", hasher.finish());
+    Ok(svg.replace(" id=\"", &format!(" id=\"{}", prefix))
+          .replace("url(#", &format!("url(#{}", prefix))
+          .replace("href=\"#", &format!("href=\"#{}", prefix)))
+}
+
+/// ----------------------------------------------------------------------------
+/// Sequence diagrams
+
+const MARGIN: usize = 10;
+const PADDING: usize = 10;
+const CHAR_WIDTH: usize = 7; // at FONT_SIZE, on average
+const FONT_SIZE: usize = 13;
+const MIN_BOX_WIDTH: usize = 60;
+const BOX_HEIGHT: usize = 30;
+const GAP: usize = 30;
+const ROW_HEIGHT: usize = 36;
+const SELF_WIDTH: usize = 30;
+const SELF_HEIGHT: usize = 20;
+const HEAD: usize = 8;
+
+lazy_static! {
+    static ref MESSAGE_RE: Regex =
+        Regex::new(r"^(.+?)\s*(--?)>>?\s*([^:]+?)\s*(?::\s*(.*))?$")
+        .expect("Wrong sequence message regexp");
+}
+
+struct Participant<'s> {
+    id: &'s str,
+    label: &'s str
+}
+
+struct Message<'s> {
+    from: usize,
+    to: usize,
+    text: &'s str,
+    dashed: bool
+}
+
+fn text_width(text: &str) -> usize {
+    text.chars().count() * CHAR_WIDTH
+}
+
+/// Index of participant `id`, declaring it if needed.
+fn participant<'s>(participants: &mut Vec<Participant<'s>>, id: &'s str, label: &'s str) -> usize {
+    match participants.iter().position(|p| p.id == id) {
+        Some(i) => i,
+        None => {
+            participants.push(Participant { id: id, label: label });
+            participants.len() - 1
+        }
+    }
+}
+
+fn parse_sequence<'s>(source: &'s str) -> Result<(Vec<Participant<'s>>, Vec<Message<'s>>), String> {
+    let mut participants = vec![];
+    let mut messages = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("%%") || line == "sequenceDiagram" {
+            continue;
+        }
+        let declared = ["participant ", "actor "].iter()
+            .find(|keyword| line.starts_with(*keyword))
+            .map(|keyword| line[keyword.len()..].trim());
+        if let Some(declared) = declared {
+            let (id, label) = match declared.find(" as ") {
+                Some(j) => (declared[..j].trim(), declared[j + 4..].trim()),
+                None => (declared, declared)
+            };
+            participant(&mut participants, id, label);
+            continue;
+        }
+        let captures = MESSAGE_RE.captures(line)
+            .ok_or_else(|| format!("line {}: expected `A -> B: message`, got: {}", i + 1, line))?;
+        let from = captures.get(1).map_or("", |m| m.as_str());
+        let to = captures.get(3).map_or("", |m| m.as_str());
+        messages.push(Message { from: participant(&mut participants, from, from),
+                                to: participant(&mut participants, to, to),
+                                text: captures.get(4).map_or("", |m| m.as_str()),
+                                dashed: captures.get(2).map_or(false, |m| m.as_str() == "--") });
+    }
+    if participants.is_empty() {
+        return Err("no participants".to_string());
+    }
+    Ok((participants, messages))
+}
+
+/// Lifelines far enough apart for the messages between them.
+fn lifelines(participants: &[Participant], widths: &[usize], messages: &[Message]) -> Vec<usize> {
+    let mut xs: Vec<usize> = vec![MARGIN + widths[0] / 2];
+    for i in 1..participants.len() {
+        let x = xs[i - 1] + widths[i - 1] / 2 + GAP + widths[i] / 2;
+        xs.push(x);
+    }
+    for m in messages {
+        let (left, right) = (m.from.min(m.to), m.from.max(m.to));
+        let (right, room) = if left == right {
+            (right + 1, SELF_WIDTH + PADDING + text_width(m.text) + PADDING)
+        } else {
+            (right, text_width(m.text) + 2 * PADDING)
+        };
+        if right < xs.len() && xs[right] - xs[left] < room {
+            let shift = room - (xs[right] - xs[left]);
+            for x in &mut xs[right..] {
+                *x += shift;
+            }
+        }
+    }
+    xs
+}
+
+fn arrow_head(x: usize, y: usize, rightwards: bool) -> String {
+    let back = if rightwards { x - HEAD } else { x + HEAD };
+    format!("{},{} {},{} {},{}", x, y, back, y - HEAD / 2, back, y + HEAD / 2)
+}
+
+fn sequence_svg(source: &str) -> Result<Markup, String> {
+    let (participants, messages) = parse_sequence(source)?;
+    let widths: Vec<usize> = participants.iter()
+        .map(|p| (text_width(p.label) + 2 * PADDING).max(MIN_BOX_WIDTH))
+        .collect();
+    let xs = lifelines(&participants, &widths, &messages);
+
+    let mut ys = vec![];
+    let mut y = MARGIN + BOX_HEIGHT + ROW_HEIGHT;
+    for m in &messages {
+        ys.push(y);
+        y += if m.from == m.to { ROW_HEIGHT + SELF_HEIGHT } else { ROW_HEIGHT };
+    }
+    let bottom = y - ROW_HEIGHT / 2;
+    let last = participants.len() - 1;
+    let width = messages.iter()
+        .filter(|m| m.from == last && m.to == last)
+        .map(|m| xs[last] + SELF_WIDTH + PADDING + text_width(m.text))
+        .fold(xs[last] + widths[last] / 2, usize::max) + MARGIN;
+    let height = bottom + BOX_HEIGHT + MARGIN;
+
+    Ok(html!(
+        svg class="sequence" xmlns="http://www.w3.org/2000/svg" width=(width) height=(height)
+            viewBox={ "0 0 " (width) " " (height) } font-family="sans-serif" font-size=(FONT_SIZE)
+            fill="currentColor" stroke="currentColor" {
+            @for (i, p) in participants.iter().enumerate() {
+                line x1=(xs[i]) y1=(MARGIN + BOX_HEIGHT) x2=(xs[i]) y2=(bottom) stroke-dasharray="3 3" {}
+                @for top in &[MARGIN, bottom] {
+                    rect x=(xs[i] - widths[i] / 2) y=(top) width=(widths[i]) height=(BOX_HEIGHT) rx="3" fill="none" {}
+                    text x=(xs[i]) y=(top + BOX_HEIGHT / 2 + FONT_SIZE / 3) text-anchor="middle" stroke="none" { (p.label) }
+                }
+            }
+            @for (m, &y) in messages.iter().zip(ys.iter()) {
+                @if m.from == m.to {
+                    polyline points={ (xs[m.from]) "," (y) " " (xs[m.from] + SELF_WIDTH) "," (y) " "
+                                      (xs[m.from] + SELF_WIDTH) "," (y + SELF_HEIGHT) " " (xs[m.from] + HEAD) "," (y + SELF_HEIGHT) }
+                        fill="none" stroke-dasharray=[if m.dashed { Some("4 3") } else { None }] {}
+                    polygon points=(arrow_head(xs[m.from], y + SELF_HEIGHT, false)) {}
+                    text x=(xs[m.from] + SELF_WIDTH + PADDING) y=(y + SELF_HEIGHT / 2 + FONT_SIZE / 3) stroke="none" { (m.text) }
+                } @else {
+                    line x1=(xs[m.from]) y1=(y) x2=(xs[m.to]) y2=(y)
+                        stroke-dasharray=[if m.dashed { Some("4 3") } else { None }] {}
+                    polygon points=(arrow_head(xs[m.to], y, m.to > m.from)) {}
+                    text x=((xs[m.from] + xs[m.to]) / 2) y=(y - 6) text-anchor="middle" stroke="none" { (m.text) }
+                }
+            }
+        }
+    ))
+}
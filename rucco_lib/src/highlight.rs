@@ -0,0 +1,92 @@
+//! Syntax highlighting settings (`[highlight]` in the ruccofile): the sublime
+//! syntaxes and the theme, loaded once and shared by every rendering thread.
+//!
+//! ```toml
+//! [highlight]
+//! syntaxes_dir = "syntaxes/"   # .sublime-syntax files, added to the bundled ones
+//! theme = "base16-ocean.dark"  # one of the bundled themes
+//! theme_file = "corp.tmTheme"  # wins over theme
+//! classes = true               # css classes instead of inline styles
+//! dark_theme = "base16-ocean.dark" # highlight.css dark variant, for classes
+//! dark_theme_file = "corp-dark.tmTheme"
+//! line_numbers = true          # every code line has a `#L123` anchor anyway
+//! ```
+//!
+//! With `classes`, the code only gets scope classes (`<span class="source rust">`)
+//! and `highlight.css` styles them after the theme, switching to the dark theme
+//! for readers preferring a dark color scheme.
+
+use std::path::Path;
+use syntect::parsing::SyntaxSet;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::css_for_theme;
+
+pub const DEFAULT_THEME: &'static str = "base16-ocean.dark";
+
+/// Class based highlighting stylesheet, at the root of the output directory.
+pub const CSS_FILE: &'static str = "highlight.css";
+
+pub struct Highlighting {
+    pub syntax_set: SyntaxSet,
+    pub theme: Theme,
+    /// scope classes instead of inline styles
+    pub classes: bool,
+    pub dark_theme: Option<Theme>,
+    /// in the gutter of code blocks, linking to their `#L123` anchor
+    pub line_numbers: bool
+}
+
+impl Highlighting {
+    /// The syntaxes and theme bundled with syntect.
+    pub fn defaults() -> Highlighting {
+        Highlighting::load(None, DEFAULT_THEME, None).expect("failed to load the default highlighting")
+    }
+
+    pub fn load(syntaxes_dir: Option<&Path>, theme: &str, theme_file: Option<&Path>) -> Result<Highlighting, String> {
+        let syntax_set = match syntaxes_dir {
+            Some(dir) => {
+                let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+                builder.add_from_folder(dir, true)
+                    .map_err(|e| format!("failed to load syntaxes from {}: {}", dir.display(), e))?;
+                builder.build()
+            },
+            None => SyntaxSet::load_defaults_newlines()
+        };
+        Ok(Highlighting { syntax_set: syntax_set, theme: load_theme(theme, theme_file)?,
+                          classes: false, dark_theme: None, line_numbers: false })
+    }
+
+    /// Class based highlighting, the dark theme (if any) being used by
+    /// `prefers-color-scheme: dark` readers.
+    pub fn with_classes(mut self, dark_theme: Option<Theme>) -> Highlighting {
+        self.classes = true;
+        self.dark_theme = dark_theme;
+        self
+    }
+
+    pub fn with_line_numbers(mut self, line_numbers: bool) -> Highlighting {
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    /// The content of `highlight.css`.
+    pub fn css(&self) -> String {
+        let mut css = css_for_theme(&self.theme);
+        if let Some(ref dark_theme) = self.dark_theme {
+            css.push_str("\n@media (prefers-color-scheme: dark) {\n");
+            css.push_str(&css_for_theme(dark_theme));
+            css.push_str("}\n");
+        }
+        css
+    }
+}
+
+/// A bundled theme, or a `.tmTheme` file (which wins).
+pub fn load_theme(name: &str, file: Option<&Path>) -> Result<Theme, String> {
+    match file {
+        Some(file) => ThemeSet::get_theme(file)
+            .map_err(|e| format!("failed to load theme {}: {}", file.display(), e)),
+        None => ThemeSet::load_defaults().themes.remove(name)
+            .ok_or_else(|| format!("unknown theme: {}", name))
+    }
+}
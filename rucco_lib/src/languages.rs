@@ -1,79 +1,480 @@
 use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::Path;
 use regex::{Regex,RegexBuilder};
 use toml;
+use literate::Literate;
+
+/// A language definition from the ruccofile, ready for the lexer.
+///
+/// The comment marks are regex fragments, the string delimiters are plain
+/// strings:
+///
+/// ```toml
+/// [languages.rs]
+/// singleline = "//[/!]?"
+/// multiline_header = "/\\*+"
+/// multiline_footer = "\\*+/"
+/// multiline_margin = "\\*"
+/// multiline_nested = true                          # /* /* */ */
+/// doc_singleline = "//[/!]"                        # doc comments, when
+/// doc_multiline_header = "/\\*[*!]"               # `comments = "doc-only"`
+/// strings = ['"']                                  # string delimiters
+/// escape = "\\"                                    # the default
+/// chars = "'"                                      # char literals ('a', '\n')
+/// raw_strings = [{ open = 'r(#*)"', close = '"$1' }] # close may use open's captures
+/// ```
+///
+/// Docstrings are string literals alone on their lines, where they document a
+/// definition (found by the `definitions` regexes below), they are doc:
+///
+/// ```toml
+/// [languages.ex]
+/// docstrings = ['"""']
+/// docstring_prefix = "@(?:module)?doc\\s+" # regex, required when given
+/// docstring_placement = "either" # "opening" (the default) or "preceding"
+/// ```
+///
+/// Literate languages have no comment marks, their code is in markdown blocks,
+/// untagged or tagged with the language's extension or `aliases` (the other
+/// blocks are examples):
+///
+/// ```toml
+/// [languages.litcoffee]
+/// literate = true      # or "bird", for bird tracks ("> code")
+/// highlight = "coffee" # extension used for syntax highlighting
+/// [languages.md]
+/// literate_suffix = true # "main.rs.md" is literate rust
+/// [languages.rs]
+/// aliases = ["rust"]     # "```rust" blocks of "main.rs.md" are code
+/// ```
+///
+/// Files without a (known) extension are recognized by name, glob or shebang
+/// (see `Languages::language_of`), and `syntax` names the sublime syntax to
+/// highlight them with:
+///
+/// ```toml
+/// [languages.make]
+/// filenames = ["Makefile", "GNUmakefile"]
+/// globs = ["*.mk", "make/*.inc"] # a glob without '/' matches the file name
+/// shebangs = ["make"]            # "#!/usr/bin/make -f"
+/// syntax = "Makefile"
+/// ```
+///
+/// Definitions are found for intra-doc links (see `symbols`), by regexes
+/// matched at the start of the code lines, past their indentation:
+///
+/// ```toml
+/// [languages.rs]
+/// definitions = ['(?:pub\s+)?fn\s+(?P<name>\w+)'] # name is the defined identifier
+/// scopes = ['impl\s+(?P<name>\w+)'] # qualify the definitions indented below
+/// ```
+#[derive(Debug,Default)]
+pub struct Syntax {
+    pub singleline: Option<Regex>,
+    pub multiline_header: Option<Regex>,
+    pub multiline_footer: Option<Regex>,
+    pub multiline_margin: Option<Regex>,
+    pub multiline_nested: bool,
+    pub doc_singleline: Option<Regex>,
+    pub doc_multiline_header: Option<Regex>,
+    /// only doc comments are doc (set from `Comments::DocOnly`)
+    pub doc_only: bool,
+    /// trailing comments are annotations of their code line (set from
+    /// `Languages::with_annotations`)
+    pub annotations: bool,
+    /// longest first, so that `"""` wins over `"`
+    pub strings: Vec<String>,
+    pub escape: Option<char>,
+    pub chars: Option<String>,
+    /// opening regex, closing template
+    pub raw_strings: Vec<(Regex, String)>,
+    /// delimiters of the docstrings, among `strings`
+    pub docstrings: Vec<String>,
+    pub docstring_prefix: Option<Regex>,
+    pub docstring_placement: Placement,
+    pub literate: Option<Literate>,
+    /// fence tags of the code of literate files: the highlight extension and
+    /// its aliases (set by `Languages::get`, any tag is code if empty)
+    pub fence_tags: Vec<String>,
+    pub highlight: Option<String>,
+    /// name of the sublime syntax, tried before `highlight`
+    pub highlight_syntax: Option<String>,
+    /// capturing the `name` of a definition
+    pub definitions: Vec<Regex>,
+    /// capturing the `name` qualifying the definitions below
+    pub scopes: Vec<Regex>
+}
+
+/// Which comments are documentation (`[input] comments`).
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Comments {
+    All,
+    /// only the comments matching `doc_singleline` or `doc_multiline_header`,
+    /// for the languages defining them
+    DocOnly
+}
+
+impl Comments {
+    pub fn from_name(name: &str) -> Option<Comments> {
+        match name {
+            "all" => Some(Comments::All),
+            "doc-only" => Some(Comments::DocOnly),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Comments::All => "all",
+            Comments::DocOnly => "doc-only"
+        }
+    }
+}
+
+/// Where docstrings stand (`docstring_placement`).
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Placement {
+    /// first in the file or in a definition's body (Python)
+    Opening,
+    /// right before a definition (Julia)
+    Preceding,
+    /// either (Elixir's `@moduledoc` and `@doc`)
+    Either
+}
+
+impl Default for Placement {
+    fn default() -> Placement {
+        Placement::Opening
+    }
+}
+
+impl Placement {
+    pub fn from_name(name: &str) -> Option<Placement> {
+        match name {
+            "opening" => Some(Placement::Opening),
+            "preceding" => Some(Placement::Preceding),
+            "either" => Some(Placement::Either),
+            _ => None
+        }
+    }
+}
+
+/// How to recognize a language, besides its extension.
+struct Detection {
+    language: String,
+    filenames: Vec<String>,
+    globs: Vec<Regex>,
+    shebangs: Vec<String>
+}
 
 // figure out Arc, Mutex etc. afterwards
 pub struct Languages {
-    computed: BTreeMap<String, Option<Regex>>,
-    raw: toml::value::Table
+    computed: BTreeMap<String, Option<Syntax>>,
+    raw: toml::value::Table,
+    detections: Vec<Detection>,
+    comments: Comments,
+    annotations: bool
 }
 
 impl Languages {
     pub fn new(raw: toml::value::Table) -> Languages {
-        Languages {computed: BTreeMap::new(), raw: raw}
+        let detections = raw.iter()
+            .filter_map(|(language, table)| detection(language, table))
+            .collect();
+        Languages {computed: BTreeMap::new(), raw: raw, detections: detections,
+                   comments: Comments::All, annotations: false}
+    }
+
+    pub fn with_comments(mut self, comments: Comments) -> Languages {
+        self.computed.clear();
+        self.comments = comments;
+        self
+    }
+
+    /// `int x; // moo`: "moo" is kept apart from the code, as a note on its line.
+    pub fn with_annotations(mut self, annotations: bool) -> Languages {
+        self.computed.clear();
+        self.annotations = annotations;
+        self
     }
 
-    pub fn get(&mut self, l: &str) -> &Option<Regex> {
-        let lang_raw_value = self.raw.get(l);
+    pub fn get(&mut self, l: &str) -> &Option<Syntax> {
+        let raw = &self.raw;
+        let comments = self.comments;
+        let annotations = self.annotations;
         let entry = self.computed.entry(l.to_owned());
         entry.or_insert_with(|| {
-            match lang_raw_value {
-                Some(lang) => compute_regex(lang),
-                None => None
-            }
+            let syntax = match raw.get(l) {
+                Some(lang) => compute_syntax(lang),
+                None => literate_double_extension(raw, l)
+            };
+            syntax.map(|mut syntax| {
+                if syntax.literate.is_some() {
+                    syntax.fence_tags = fence_tags(raw, syntax.highlight.as_ref().map_or(l, String::as_str));
+                }
+                syntax.doc_only = comments == Comments::DocOnly
+                    && (syntax.doc_singleline.is_some() || syntax.doc_multiline_header.is_some()
+                        || !syntax.docstrings.is_empty());
+                syntax.annotations = annotations;
+                syntax
+            })
         })
     }
-}
 
-pub fn compute_regex(language: &toml::Value) -> Option<Regex> {
-    let table = language.as_table().unwrap();
-    let singleline_mark = table.get("singleline")
-        .map(|v| v.as_str().expect("MALFORMED RUCCOFILE"));
-    let multiline_header_mark = table.get("multiline_header")
-        .map(|v| v.as_str().expect("MALFORMED RUCCOFILE"));
-    let multiline_footer_mark = table.get("multiline_footer")
-        .map(|v| v.as_str().expect("MALFORMED RUCCOFILE"));
-    let multiline_margin_mark = table.get("multiline_margin")
-        .map(|v| v.as_str().expect("MALFORMED RUCCOFILE"));
+    /// The language of a file, first found of:
+    ///
+    /// 1. `filenames`: its whole name ("Makefile", ".bashrc")
+    /// 2. `globs`: its path ("*.mk")
+    /// 3. its extension, or both its extensions for literate files like
+    ///    "main.rs.md" ("rs.md")
+    /// 4. `shebangs`: the interpreter of its first line, if given
+    ///    ("#!/usr/bin/env python3" -> "python3", or else "python")
+    ///
+    /// The first line is only needed when the path is not enough.
+    pub fn language_of(&self, path: &Path, first_line: Option<&str>) -> Option<String> {
+        let name = path.file_name().and_then(OsStr::to_str)?;
+        if let Some(d) = self.detections.iter().find(|d| d.filenames.iter().any(|f| f == name)) {
+            return Some(d.language.clone());
+        }
+        let unix_path = path.to_str()?.replace('\\', "/");
+        if let Some(d) = self.detections.iter().find(|d| d.globs.iter().any(|g| g.is_match(&unix_path))) {
+            return Some(d.language.clone());
+        }
+        if let Some(extension) = self.extension_language(path) {
+            return Some(extension);
+        }
+        let program = first_line.and_then(interpreter)?;
+        let unversioned = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        [program, unversioned].iter()
+            .filter_map(|p| self.detections.iter().find(|d| d.shebangs.iter().any(|s| s == p)))
+            .map(|d| d.language.clone())
+            .next()
+    }
+
+    fn extension_language(&self, path: &Path) -> Option<String> {
+        let extension = path.extension().and_then(OsStr::to_str)?;
+        let inner = path.file_stem()
+            .and_then(|stem| Path::new(stem).extension())
+            .and_then(OsStr::to_str);
+        match inner {
+            Some(inner) if is_literate_suffix(&self.raw, extension) && self.raw.contains_key(inner) =>
+                Some([inner, ".", extension].concat()),
+            _ if self.raw.contains_key(extension) => Some(extension.to_owned()),
+            _ => None
+        }
+    }
+
+    /// Extension to highlight a language's code with.
+    pub fn highlight_extension(&mut self, l: &str) -> String {
+        match self.get(l) {
+            &Some(Syntax { highlight: Some(ref highlight), .. }) => highlight.clone(),
+            _ => l.to_owned()
+        }
+    }
+
+    /// Sublime syntax to highlight a language's code with, if named.
+    pub fn highlight_syntax(&mut self, l: &str) -> Option<String> {
+        match self.get(l) {
+            &Some(ref syntax) => syntax.highlight_syntax.clone(),
+            &None => None
+        }
+    }
+}
 
-    let mut regexp_vec: Vec<String> = Vec::new();
-    regexp_vec.push(r"(?:".to_string()); // global group
-    regexp_vec.push(r"(?:\n+)|".to_string()); // empty lines
-    if let Some(sl) = singleline_mark {
-        // singleline
-        regexp_vec.push([r"(?:",
-                         r"^[ \t]*", sl, r" ?",
-                         r"(?:(?P<doc_sl>[^\n]*\n?)\n*)",
-                         r")|"].concat());
+fn detection(language: &str, table: &toml::Value) -> Option<Detection> {
+    let table = table.as_table().expect("MALFORMED RUCCOFILE");
+    let strings = |key: &str| -> Vec<String> {
+        table.get(key)
+            .map(|v| v.as_array().expect("MALFORMED RUCCOFILE").iter()
+                 .map(|s| s.as_str().expect("MALFORMED RUCCOFILE").to_owned())
+                 .collect())
+            .unwrap_or_default()
     };
-    if let (Some(mh), Some(mf), Some(mm)) = (multiline_header_mark, multiline_footer_mark, multiline_margin_mark) {
-        // multiline with margin
-        regexp_vec.push([r"(?:",
-                         r"^[ \t]*", mh, r"(?P<doc_ml_h>[^\n]*\n?)\n*", // header and potential doc there
-                         r"(?P<doc_ml_l>(?:[ \t]*", mm, r"[^\n]*\n*)*[ \t]*)", mf, // lines
-                         r")|"].concat());
-        // this is far from foolproof
+    let detection = Detection {
+        language: language.to_owned(),
+        filenames: strings("filenames"),
+        globs: strings("globs").iter()
+            .map(|g| glob_regex(g).expect("MALFORMED RUCCOFILE - bad glob"))
+            .collect(),
+        shebangs: strings("shebangs")
     };
-    if let (Some(mh), Some(mf)) = (multiline_header_mark, multiline_footer_mark) {
-        // multiline without margin
-        regexp_vec.push([r"(?:",
-                         r"^[ \t]*", mh, r"(?P<doc_ml>.*?)", mf,
-                         r")|"].concat());
+    if detection.filenames.is_empty() && detection.globs.is_empty() && detection.shebangs.is_empty() {
+        None
+    } else {
+        Some(detection)
+    }
+}
+
+/// "*.mk" -> matches "Rules.mk" and "src/Rules.mk"; "**" goes through
+/// directories, '*' and '?' don't.
+fn glob_regex(glob: &str) -> Result<Regex, ::regex::Error> {
+    let mut pattern = String::from(r"(?:\A|/)");
+    let mut chars = glob.trim_start_matches("./").chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            },
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&::regex::escape(&c.to_string()))
+        }
+    }
+    pattern.push_str(r"\z");
+    Regex::new(&pattern)
+}
+
+/// "#!/usr/bin/env -S python3 -u" -> "python3"
+fn interpreter(first_line: &str) -> Option<&str> {
+    if !first_line.starts_with("#!") {
+        return None;
+    }
+    let mut words = first_line[2..].split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        words.find(|w| !w.starts_with('-') && !w.contains('='))
+    } else {
+        Some(program)
+    }
+}
+
+/// "rs" -> ["rs", "rust"]
+fn fence_tags(raw: &toml::value::Table, language: &str) -> Vec<String> {
+    let aliases = raw.get(language)
+        .and_then(|l| l.as_table())
+        .and_then(|l| l.get("aliases"))
+        .map(|v| v.as_array().expect("MALFORMED RUCCOFILE").iter()
+             .map(|s| s.as_str().expect("MALFORMED RUCCOFILE").to_owned())
+             .collect())
+        .unwrap_or_default();
+    [vec![language.to_owned()], aliases].concat()
+}
+
+fn is_literate_suffix(raw: &toml::value::Table, extension: &str) -> bool {
+    raw.get(extension)
+        .and_then(|l| l.as_table())
+        .and_then(|l| l.get("literate_suffix"))
+        .map_or(false, |v| v.as_bool().expect("MALFORMED RUCCOFILE"))
+}
+
+/// "rs.md" -> literate markdown, highlighted as "rs"
+fn literate_double_extension(raw: &toml::value::Table, l: &str) -> Option<Syntax> {
+    let dot = l.rfind('.')?;
+    let (inner, suffix) = (&l[..dot], &l[dot + 1..]);
+    if is_literate_suffix(raw, suffix) && raw.contains_key(inner) {
+        let table = raw[inner].as_table().expect("MALFORMED RUCCOFILE");
+        let list = |key: &str| anchored_list(table, key).unwrap_or_else(|e| {
+            error!("Failed to build {} of language {}: {}", key, inner, e);
+            vec![]
+        });
+        Some(Syntax { literate: Some(Literate::Markdown),
+                      highlight: Some(inner.to_owned()),
+                      definitions: list("definitions"),
+                      scopes: list("scopes"),
+                      ..Syntax::default() })
+    } else {
+        None
+    }
+}
+
+/// Marks only ever match where the lexer stands.
+fn anchored(mark: &str) -> Result<Regex, ::regex::Error> {
+    RegexBuilder::new(&[r"\A(?:", mark, r")"].concat()).build()
+}
+
+fn anchored_list(table: &toml::value::Table, key: &str) -> Result<Vec<Regex>, ::regex::Error> {
+    match table.get(key) {
+        Some(marks) => marks.as_array().expect("MALFORMED RUCCOFILE").iter()
+            .map(|m| anchored(m.as_str().expect("MALFORMED RUCCOFILE")))
+            .collect(),
+        None => Ok(vec![])
+    }
+}
+
+fn build_syntax(table: &toml::value::Table) -> Result<Syntax, ::regex::Error> {
+    let mark = |key: &str| table.get(key)
+        .map(|v| v.as_str().expect("MALFORMED RUCCOFILE"));
+    let regex = |key: &str| match mark(key) {
+        Some(m) => anchored(m).map(Some),
+        None => Ok(None)
     };
-    regexp_vec.push(r"(?:^(?P<code>[^\n]*\n?)\n*)".to_string()); // codeline
-    regexp_vec.push(r")".to_string()); // global group end and repeat
-
-    let final_regexp = regexp_vec.concat();
-    debug!("building regexp from: {}", &final_regexp);
-
-    match RegexBuilder::new(&final_regexp)
-        .multi_line(true)
-        .dot_matches_new_line(true)
-        .build() {
-            Ok(regexp) => Some(regexp),
-            Err(e) => {
-                error!("Failed to build regex from language {:?}: {}", language, e);
-                None
-            }
+
+    let delimiters = |key: &str| -> Vec<String> {
+        let mut delimiters: Vec<String> = table.get(key)
+            .map(|v| v.as_array().expect("MALFORMED RUCCOFILE").iter()
+                 .map(|s| s.as_str().expect("MALFORMED RUCCOFILE").to_owned())
+                 .collect())
+            .unwrap_or_default();
+        delimiters.sort_by(|a, b| b.len().cmp(&a.len()));
+        delimiters
+    };
+
+    let mut raw_strings = vec![];
+    if let Some(raws) = table.get("raw_strings") {
+        for raw in raws.as_array().expect("MALFORMED RUCCOFILE") {
+            let raw = raw.as_table().expect("MALFORMED RUCCOFILE");
+            let get = |key: &str| raw.get(key)
+                .and_then(|v| v.as_str()).expect("MALFORMED RUCCOFILE");
+            raw_strings.push((anchored(get("open"))?, get("close").to_owned()));
         }
+    }
+
+    Ok(Syntax {
+        singleline: regex("singleline")?,
+        multiline_header: regex("multiline_header")?,
+        multiline_footer: regex("multiline_footer")?,
+        multiline_margin: regex("multiline_margin")?,
+        multiline_nested: table.get("multiline_nested")
+            .map_or(false, |v| v.as_bool().expect("MALFORMED RUCCOFILE")),
+        doc_singleline: regex("doc_singleline")?,
+        doc_multiline_header: regex("doc_multiline_header")?,
+        doc_only: false,
+        annotations: false,
+        strings: delimiters("strings"),
+        escape: match mark("escape") {
+            Some(e) => e.chars().next(),
+            None => Some('\\')
+        },
+        chars: mark("chars").map(str::to_owned),
+        raw_strings: raw_strings,
+        docstrings: delimiters("docstrings"),
+        docstring_prefix: regex("docstring_prefix")?,
+        docstring_placement: match mark("docstring_placement") {
+            Some(name) => Placement::from_name(name)
+                .expect("MALFORMED RUCCOFILE - docstring_placement is \"opening\", \"preceding\" or \"either\""),
+            None => Placement::default()
+        },
+        literate: match table.get("literate") {
+            Some(&toml::Value::Boolean(true)) => Some(Literate::Markdown),
+            Some(&toml::Value::String(ref style)) if style == "bird" => Some(Literate::Bird),
+            Some(&toml::Value::String(ref style)) if style == "markdown" => Some(Literate::Markdown),
+            Some(&toml::Value::Boolean(false)) | None => None,
+            Some(_) => panic!("MALFORMED RUCCOFILE - literate is true, false, \"markdown\" or \"bird\"")
+        },
+        fence_tags: vec![],
+        highlight: mark("highlight").map(str::to_owned),
+        highlight_syntax: mark("syntax").map(str::to_owned),
+        definitions: anchored_list(table, "definitions")?,
+        scopes: anchored_list(table, "scopes")?
+    })
+}
+
+pub fn compute_syntax(language: &toml::Value) -> Option<Syntax> {
+    match build_syntax(language.as_table().expect("MALFORMED RUCCOFILE")) {
+        Ok(syntax) => {
+            debug!("built syntax: {:?}", &syntax);
+            Some(syntax)
+        },
+        Err(e) => {
+            error!("Failed to build syntax from language {:?}: {}", language, e);
+            None
+        }
+    }
 }
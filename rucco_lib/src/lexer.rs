@@ -0,0 +1,333 @@
+//! Splits a source file into code, comments and newlines, following its
+//! `languages::Syntax`.
+//!
+//! String and char literals are skipped over as code, so that comment marks
+//! inside them (`"http://x"`, `'#'`) are left alone. A string literal can span
+//! several lines, comments never start inside one.
+//!
+//! Docstrings are string literals alone on their lines (`"""Summary."""`),
+//! possibly after a `docstring_prefix` (Elixir's `@doc`), where they document
+//! a definition: opening the file or the definition's body, or preceding the
+//! definition, as per the language's `docstring_placement`. Elsewhere they are
+//! code, like Julia's `"hello"` return value or Python's SQL argument.
+//!
+//! A shebang (`#!/usr/bin/env python`) on the first line is code, whatever the
+//! comment marks: it is no documentation.
+
+use languages::{Placement, Syntax};
+
+use regex::Regex;
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Token<'t> {
+    /// code up to the end of the line (or further, for multiline strings)
+    Code(&'t str),
+    /// `text` is the whole comment, `body` what follows the mark (no newline)
+    LineComment { text: &'t str, body: &'t str },
+    /// `text` is the whole comment, `body` what is between the marks
+    BlockComment { text: &'t str, body: &'t str },
+    /// `text` is the whole literal (prefix included), `body` its content
+    Docstring { text: &'t str, body: &'t str },
+    Newline
+}
+
+impl<'t> Token<'t> {
+    pub fn text(&self) -> &'t str {
+        match self {
+            &Token::Code(text) => text,
+            &Token::LineComment { text, .. } => text,
+            &Token::BlockComment { text, .. } => text,
+            &Token::Docstring { text, .. } => text,
+            &Token::Newline => "\n"
+        }
+    }
+
+    /// whitespace only code
+    pub fn is_blank(&self) -> bool {
+        match self {
+            &Token::Code(text) => text.trim().is_empty(),
+            _ => false
+        }
+    }
+}
+
+/// Longest a char literal can be, escape included ('\u{10FFFF}').
+const MAX_CHAR_LITERAL: usize = 10;
+
+/// Iterator<Item=Token>
+pub struct Lexer<'s, 't> {
+    syntax: &'s Syntax,
+    source: &'t str,
+    pos: usize
+}
+
+pub fn tokenize<'s, 't>(syntax: &'s Syntax, source: &'t str) -> Lexer<'s, 't> {
+    Lexer { syntax: syntax, source: source, pos: 0 }
+}
+
+impl<'s, 't> Lexer<'s, 't> {
+    /// End of the single line comment starting at `pos`, and where its body starts.
+    fn line_comment_at(&self, pos: usize) -> Option<(usize, usize)> {
+        let rest = &self.source[pos..];
+        self.syntax.singleline.as_ref()
+            .and_then(|r| r.find(rest))
+            .map(|mark| {
+                let end = rest.find('\n').unwrap_or(rest.len());
+                (pos + end, pos + mark.end().min(end))
+            })
+    }
+
+    /// End of the block comment starting at `pos`, and its body bounds.
+    /// Nested comments (`multiline_nested`) close with their last footer.
+    /// An unterminated block comment runs to the end of the source.
+    fn block_comment_at(&self, pos: usize) -> Option<(usize, (usize, usize))> {
+        let (header, footer) = match (&self.syntax.multiline_header, &self.syntax.multiline_footer) {
+            (&Some(ref header), &Some(ref footer)) => (header, footer),
+            _ => return None
+        };
+        let body_start = pos + header.find(&self.source[pos..])?.end();
+        let mut depth = 1;
+        // the footer may share characters with a greedy header: "/**/"
+        let mut i = pos + 1;
+        while i < self.source.len() {
+            let rest = &self.source[i..];
+            if let Some(m) = footer.find(rest) {
+                if i + m.end() > body_start {
+                    depth -= 1;
+                    if depth == 0 || !self.syntax.multiline_nested {
+                        return Some((i + m.end(), (body_start, i.max(body_start))));
+                    }
+                    i += m.end();
+                    continue;
+                }
+            }
+            if self.syntax.multiline_nested && i >= body_start {
+                if let Some(opener) = shortest_match(header, rest) {
+                    depth += 1;
+                    i += opener;
+                    continue;
+                }
+            }
+            i += self.char_len(i);
+        }
+        Some((self.source.len(), (body_start, self.source.len())))
+    }
+
+    /// End of the string or char literal starting at `pos`.
+    fn literal_at(&self, pos: usize) -> Option<usize> {
+        let rest = &self.source[pos..];
+        if !self.syntax.raw_strings.is_empty() && !self.follows_identifier(pos) {
+            for &(ref open, ref close) in &self.syntax.raw_strings {
+                if let Some(captures) = open.captures(rest) {
+                    let opened = captures.get(0).expect("no whole match").end();
+                    let mut closing = String::new();
+                    captures.expand(close, &mut closing);
+                    return Some(match rest[opened..].find(&closing) {
+                        Some(i) => pos + opened + i + closing.len(),
+                        None => self.source.len()
+                    });
+                }
+            }
+        }
+        for delimiter in &self.syntax.strings {
+            if rest.starts_with(delimiter.as_str()) {
+                return Some(self.string_end(pos + delimiter.len(), delimiter));
+            }
+        }
+        if let Some(ref delimiter) = self.syntax.chars {
+            if rest.starts_with(delimiter.as_str()) {
+                // or else a lifetime, a type variable, a prime...
+                return self.char_end(pos + delimiter.len(), delimiter);
+            }
+        }
+        None
+    }
+
+    /// End of the closing `delimiter`, searching from `pos` and skipping
+    /// escaped chars. An unterminated string runs to the end of the source.
+    fn string_end(&self, pos: usize, delimiter: &str) -> usize {
+        let mut chars = self.source[pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            if self.source[pos + i..].starts_with(delimiter) {
+                return pos + i + delimiter.len();
+            }
+            if Some(c) == self.syntax.escape {
+                chars.next();
+            }
+        }
+        self.source.len()
+    }
+
+    /// End of the char literal whose content starts at `pos`: a single char,
+    /// or an escape sequence, followed by `delimiter` on the same line.
+    fn char_end(&self, pos: usize, delimiter: &str) -> Option<usize> {
+        let mut chars = self.source[pos..].char_indices();
+        match chars.next() {
+            Some((_, c)) if Some(c) == self.syntax.escape => {
+                chars.next();
+                chars.take(MAX_CHAR_LITERAL)
+                    .take_while(|&(_, c)| c != '\n')
+                    .find(|&(i, _)| self.source[pos + i..].starts_with(delimiter))
+                    .map(|(i, _)| pos + i + delimiter.len())
+            },
+            Some((_, c)) if c != '\n' && !delimiter.starts_with(c) => {
+                let i = c.len_utf8();
+                if self.source[pos + i..].starts_with(delimiter) {
+                    Some(pos + i + delimiter.len())
+                } else {
+                    None
+                }
+            },
+            _ => None
+        }
+    }
+
+    /// A docstring starting at `pos`: a `docstrings` literal, after the
+    /// `docstring_prefix` if any, first and last thing on its line(s), and
+    /// placed to document a definition.
+    fn docstring_at(&self, pos: usize) -> Option<Token<'t>> {
+        if self.syntax.docstrings.is_empty() {
+            return None;
+        }
+        let line_start = self.source[..pos].rfind('\n').map_or(0, |i| i + 1);
+        if !self.source[line_start..pos].trim().is_empty() {
+            return None;
+        }
+        let opening = match self.syntax.docstring_prefix {
+            Some(ref prefix) => pos + prefix.find(&self.source[pos..])?.end(),
+            None => pos
+        };
+        let delimiter = self.syntax.docstrings.iter()
+            .find(|d| self.source[opening..].starts_with(d.as_str()))?;
+        let body_start = opening + delimiter.len();
+        let end = self.string_end(body_start, delimiter);
+        let line_end = self.source[end..].find('\n').map_or(self.source.len(), |i| end + i);
+        if !self.source[end..line_end].trim().is_empty() {
+            return None;
+        }
+        if !self.documents_definition(line_start, line_end) {
+            return None;
+        }
+        let body_end = if self.source[body_start..end].ends_with(delimiter.as_str()) {
+            end - delimiter.len()
+        } else {
+            end // unterminated
+        };
+        Some(Token::Docstring { text: &self.source[pos..end],
+                                body: &self.source[body_start..body_end.max(body_start)] })
+    }
+
+    /// Whether the docstring lines `start..end` document a definition.
+    fn documents_definition(&self, start: usize, end: usize) -> bool {
+        match self.syntax.docstring_placement {
+            Placement::Opening => self.opens_body(start),
+            Placement::Preceding => self.precedes_definition(end),
+            Placement::Either => self.opens_body(start) || self.precedes_definition(end)
+        }
+    }
+
+    /// No code before `start`, or a definition's header right before it
+    /// (from its first line: `def f(\n    x,\n):`).
+    fn opens_body(&self, start: usize) -> bool {
+        let mut brackets = 0;
+        for line in self.source[..start].lines().rev().map(str::trim).filter(|l| self.is_code_line(l)) {
+            brackets += line.matches(|c| c == ')' || c == ']' || c == '}').count() as isize;
+            brackets -= line.matches(|c| c == '(' || c == '[' || c == '{').count() as isize;
+            if brackets <= 0 {
+                return self.is_definition(line);
+            }
+        }
+        true
+    }
+
+    /// A definition right after `end`, past annotations (`@spec`, `@inline`).
+    fn precedes_definition(&self, end: usize) -> bool {
+        self.source[end..].lines().map(str::trim)
+            .filter(|l| self.is_code_line(l))
+            .find(|l| !l.starts_with('@') || self.is_definition(l))
+            .map_or(false, |l| self.is_definition(l))
+    }
+
+    fn is_definition(&self, line: &str) -> bool {
+        self.syntax.definitions.iter().chain(self.syntax.scopes.iter()).any(|r| r.is_match(line))
+    }
+
+    /// neither blank nor a single line comment
+    fn is_code_line(&self, line: &str) -> bool {
+        !line.is_empty() && !self.syntax.singleline.as_ref()
+            .and_then(|r| r.find(line))
+            .map_or(false, |mark| mark.end() > 0)
+    }
+
+    /// `r"..."` is a raw string, `bar"..."` is not.
+    fn follows_identifier(&self, pos: usize) -> bool {
+        self.source[..pos].chars().next_back()
+            .map_or(false, |c| c.is_alphanumeric() || c == '_')
+    }
+
+    fn char_len(&self, pos: usize) -> usize {
+        self.source[pos..].chars().next().map_or(1, char::len_utf8)
+    }
+
+    fn comment_at(&self, pos: usize) -> Option<Token<'t>> {
+        if let Some((end, (body_start, body_end))) = self.block_comment_at(pos) {
+            return Some(Token::BlockComment { text: &self.source[pos..end],
+                                              body: &self.source[body_start..body_end] });
+        }
+        self.line_comment_at(pos).map(|(end, body_start)| {
+            Token::LineComment { text: &self.source[pos..end],
+                                 body: &self.source[body_start..end] }
+        })
+    }
+}
+
+/// Length of the shortest non empty match of an anchored `regex`: "/*" for
+/// `/\*+` in "/**/", whose "*/" then closes it.
+fn shortest_match(regex: &Regex, text: &str) -> Option<usize> {
+    let longest = regex.find(text).map(|m| m.end()).filter(|&end| end > 0)?;
+    text[..longest].char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .find(|&end| regex.find(&text[..end]).map_or(false, |m| m.end() == end))
+}
+
+impl<'s, 't> Iterator for Lexer<'s, 't> {
+    type Item=Token<'t>;
+
+    fn next(&mut self) -> Option<Token<'t>> {
+        let start = self.pos;
+        if start >= self.source.len() {
+            return None;
+        }
+        if self.source[start..].starts_with('\n') {
+            self.pos += 1;
+            return Some(Token::Newline);
+        }
+        if start == 0 && self.source.starts_with("#!") && !self.source.starts_with("#![") {
+            self.pos = self.source.find('\n').unwrap_or(self.source.len());
+            return Some(Token::Code(&self.source[..self.pos]));
+        }
+        if let Some(comment) = self.comment_at(start).filter(|c| !c.text().is_empty()) {
+            self.pos += comment.text().len();
+            return Some(comment);
+        }
+        if let Some(docstring) = self.docstring_at(start) {
+            self.pos += docstring.text().len();
+            return Some(docstring);
+        }
+        let mut end = start;
+        while end < self.source.len() && !self.source[end..].starts_with('\n') {
+            if end > start && self.comment_at(end).is_some() {
+                break;
+            }
+            if end > start && self.source[start..end].trim().is_empty() && self.docstring_at(end).is_some() {
+                break;
+            }
+            end = match self.literal_at(end) {
+                Some(literal_end) => literal_end,
+                None => end + self.char_len(end)
+            };
+        }
+        self.pos = end;
+        Some(Token::Code(&self.source[start..end]))
+    }
+}
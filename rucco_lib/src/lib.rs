@@ -3,15 +3,33 @@
 #[macro_use] extern crate maud;
 #[macro_use] extern crate lazy_static;
 #[macro_use] extern crate log;
+#[macro_use] extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+extern crate tera;
 extern crate toml;
 extern crate regex;
-extern crate hoedown;
+extern crate pulldown_cmark;
 extern crate syntect;
+extern crate layout;
 
 pub mod segment;
+pub mod lexer;
+pub mod literate;
 pub mod languages;
 pub mod templates;
+pub mod highlight;
+pub mod math;
+pub mod markdown;
+pub mod symbols;
+pub mod diagram;
 pub mod render;
+pub mod outline;
+pub mod links;
+pub mod search;
+pub mod tangle;
 
 pub use languages::Languages;
-pub use render::render;
+pub use highlight::Highlighting;
+pub use markdown::Markdown;
+pub use render::{render, render_segments, render_raw_segments, raw_segments};
@@ -0,0 +1,268 @@
+//! Links between the documented files.
+//!
+//! Doc comments can point at other documented files, either with a wiki-like
+//! `[[src/main.rs#conf-files]]` (path relative to the project root, optional
+//! anchor, optional `|label`), or with a plain markdown link to the source
+//! file, relative to the current one (`[conf](../main.rs#conf-files)`).
+//! Both get resolved to the generated page, and the ones pointing at files or
+//! anchors that do not exist are reported. Anchors are those of the outline,
+//! the ids of the rendered docs (footnotes, `{#id}` headings) and the source
+//! lines (`#L42`).
+//!
+//! Intra-doc links, like rustdoc's [`Languages::get`], point at the line
+//! defining a symbol (see `symbols`), in the same file if it defines one.
+//! The ones naming no known symbol are reported.
+
+use outline;
+use regex::{Captures, Regex};
+use segment::Segment;
+use symbols::{self, Definition};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+lazy_static! {
+    static ref WIKI_RE: Regex =
+        Regex::new(r"\[\[([^\]|#]*)(?:#([^\]|]*))?(?:\|([^\]]*))?\]\]")
+        .expect("Wrong wiki link regexp");
+    /// relative links in rendered html (no scheme)
+    static ref HREF_RE: Regex =
+        Regex::new(r##"href="([^"#:]*)(?:#([^"]*))?""##)
+        .expect("Wrong href regexp");
+    /// relative links in raw markdown (no scheme)
+    static ref MD_LINK_RE: Regex =
+        Regex::new(r"\]\(([^)\s#:]*)(?:#([^)\s]*))?\)")
+        .expect("Wrong markdown link regexp");
+    /// [`path::to::symbol`] in rendered html, not a link already
+    static ref SYMBOL_HTML_RE: Regex =
+        Regex::new(r"\[<code>([A-Za-z_]\w*(?:(?:::|\.)[A-Za-z_]\w*)*(?:\(\)|!)?)</code>\]")
+        .expect("Wrong symbol link regexp");
+    /// [`path::to::symbol`] in raw markdown, followed by `(` or `[` if a link already
+    static ref SYMBOL_MD_RE: Regex =
+        Regex::new(r"\[`([A-Za-z_]\w*(?:(?:::|\.)[A-Za-z_]\w*)*(?:\(\)|!)?)`\]([(\[])?")
+        .expect("Wrong symbol link regexp");
+    /// ids in rendered html, `{#id}` heading attributes in raw markdown
+    static ref ID_RE: Regex =
+        Regex::new(r#"\sid="([^"]+)"|\{#([^\s}]+)[^}]*\}"#)
+        .expect("Wrong id regexp");
+    static ref LINE_ANCHOR_RE: Regex =
+        Regex::new(r"\AL([1-9][0-9]*)\z")
+        .expect("Wrong line anchor regexp");
+}
+
+/// "src/./a/../main.rs" -> "src/main.rs", without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => { normalized.pop(); },
+            c => normalized.push(c.as_os_str())
+        }
+    }
+    normalized
+}
+
+/// A documented file.
+struct Page {
+    docfile: PathBuf,
+    anchors: HashSet<String>,
+    /// its last source line, anchored by `#L{line}`
+    lines: usize
+}
+
+impl Page {
+    fn has_anchor(&self, anchor: &str) -> bool {
+        self.anchors.contains(anchor) || LINE_ANCHOR_RE.captures(anchor)
+            .and_then(|c| c[1].parse::<usize>().ok())
+            .map_or(false, |line| line <= self.lines)
+    }
+}
+
+/// Every documented file, with its generated page and anchors.
+#[derive(Default)]
+pub struct Links {
+    pages: HashMap<PathBuf, Page>,
+    /// in the order the files were added
+    symbols: Vec<(PathBuf, Definition)>
+}
+
+/// A link, once resolved.
+enum Target {
+    Found(String),
+    Broken(String)
+}
+
+impl Links {
+    pub fn new() -> Links {
+        Links::default()
+    }
+
+    /// `source` is relative to the project root, `docfile` to the output dir.
+    pub fn add_page(&mut self, source: &Path, docfile: &Path, segments: &[Segment]) {
+        let mut anchors: HashSet<String> = outline::anchors(segments).into_iter().collect();
+        let mut lines = 0;
+        for segment in segments {
+            match segment {
+                &Segment::Title((_, ref text)) | &Segment::Doc(ref text) =>
+                    anchors.extend(ID_RE.captures_iter(text)
+                                   .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+                                   .map(|m| m.as_str().to_owned())),
+                &Segment::Code((_, _, ref numbers)) =>
+                    lines = numbers.iter().cloned().fold(lines, usize::max)
+            }
+        }
+        self.pages.insert(normalize(source), Page { docfile: docfile.to_owned(), anchors: anchors, lines: lines });
+    }
+
+    /// The symbols `source` defines, once added as a page.
+    pub fn add_symbols(&mut self, source: &Path, definitions: Vec<Definition>) {
+        let source = normalize(source);
+        self.symbols.extend(definitions.into_iter().map(|d| (source.clone(), d)));
+    }
+
+    /// Where a symbol link points: the definition in `source` if any, or else
+    /// the least qualified one, first added first.
+    fn symbol_target(&self, source: &Path, root: &str, link: &str, html: bool) -> Option<String> {
+        let path = symbols::link_path(link);
+        let source = normalize(source);
+        let (file, definition) = self.symbols.iter()
+            .filter(|&&(_, ref d)| symbols::matches(&d.path, &path))
+            .min_by_key(|&&(ref file, ref d)| (*file != source, d.path.len()))?;
+        let page = self.pages.get(file)?;
+        let mut href = [root, page.docfile.to_str().expect("failed to convert doc file path to string")].concat();
+        if html {
+            href.push_str(&format!("#L{}", definition.line));
+        }
+        Some(href)
+    }
+
+    fn target(&self, source: &Path, root: &str, path: &Path, anchor: Option<&str>) -> Target {
+        let normalized = normalize(path);
+        let same_page = normalized.as_os_str().is_empty();
+        let key = if same_page { normalize(source) } else { normalized };
+        let page = match self.pages.get(&key) {
+            Some(page) => page,
+            None => return Target::Broken(path.display().to_string())
+        };
+        let mut href = if same_page {
+            String::new()
+        } else {
+            [root, page.docfile.to_str().expect("failed to convert doc file path to string")].concat()
+        };
+        if let Some(anchor) = anchor {
+            if !page.has_anchor(anchor) {
+                return Target::Broken(format!("{}#{}", path.display(), anchor));
+            }
+            href.push('#');
+            href.push_str(anchor);
+        }
+        Target::Found(href)
+    }
+
+    /// `[[path#anchor|label]]`, path being relative to the project root.
+    fn resolve_wiki_links(&self, source: &Path, root: &str, text: &str, html: bool,
+                          broken: &mut Vec<String>) -> String {
+        WIKI_RE.replace_all(text, |c: &Captures| {
+            let path = c.get(1).map_or("", |m| m.as_str());
+            let anchor = c.get(2).map(|m| m.as_str());
+            let label = c.get(3).map_or(&c[0][2..c[0].len() - 2], |m| m.as_str());
+            match self.target(source, root, Path::new(path), anchor) {
+                Target::Found(href) => if html {
+                    format!("<a href=\"{}\">{}</a>", href, label)
+                } else {
+                    format!("[{}]({})", label, href)
+                },
+                Target::Broken(link) => {
+                    broken.push(link);
+                    c[0].to_owned()
+                }
+            }
+        }).into_owned()
+    }
+
+    /// Plain links to source files, relative to the current one. Links to
+    /// anything that is not documented are left alone.
+    fn resolve_relative_links(&self, source: &Path, root: &str, text: &str, html: bool,
+                              broken: &mut Vec<String>) -> String {
+        let re: &Regex = if html { &*HREF_RE } else { &*MD_LINK_RE };
+        re.replace_all(text, |c: &Captures| {
+            let path = c.get(1).map_or("", |m| m.as_str());
+            let anchor = c.get(2).map(|m| m.as_str());
+            let relative = if path.is_empty() {
+                PathBuf::new()
+            } else {
+                source.parent().unwrap_or(Path::new("")).join(path)
+            };
+            let documented = path.is_empty() || self.pages.contains_key(&normalize(&relative));
+            if path.starts_with('/') || !documented {
+                if !path.is_empty() && !path.starts_with('/') && !relative.exists() {
+                    broken.push(path.to_owned());
+                }
+                return c[0].to_owned();
+            }
+            match self.target(source, root, &relative, anchor) {
+                Target::Found(href) => if html {
+                    format!("href=\"{}\"", href)
+                } else {
+                    format!("]({})", href)
+                },
+                Target::Broken(link) => {
+                    broken.push(link);
+                    c[0].to_owned()
+                }
+            }
+        }).into_owned()
+    }
+
+    /// Turns [`Languages::get`] into a link to the definition of `get`. The
+    /// unknown symbols are left alone, and returned.
+    pub fn resolve_symbols(&self, source: &Path, root: &str, segments: &[Segment], html: bool)
+                           -> (Vec<Segment>, Vec<String>) {
+        let mut unresolved = vec![];
+        let re: &Regex = if html { &*SYMBOL_HTML_RE } else { &*SYMBOL_MD_RE };
+        let mut resolve_text = |text: &str| re.replace_all(text, |c: &Captures| {
+            if c.get(2).is_some() {
+                return c[0].to_owned();
+            }
+            match self.symbol_target(source, root, &c[1], html) {
+                Some(href) => if html {
+                    format!("<a href=\"{}\"><code>{}</code></a>", href, &c[1])
+                } else {
+                    format!("[`{}`]({})", &c[1], href)
+                },
+                None => {
+                    unresolved.push(c[1].to_owned());
+                    c[0].to_owned()
+                }
+            }
+        }).into_owned();
+        let resolved = segments.iter().map(|segment| match segment {
+            &Segment::Title((level, ref text)) => Segment::Title((level, resolve_text(text))),
+            &Segment::Doc(ref text) => Segment::Doc(resolve_text(text)),
+            code => code.clone()
+        }).collect();
+        (resolved, unresolved)
+    }
+
+    /// Rewrites the links in the doc and title segments of `source`, whose
+    /// page is `root` away from the output dir. `html` tells if the segments
+    /// are rendered or raw markdown.
+    /// Also returns the broken links.
+    pub fn resolve(&self, source: &Path, root: &str, segments: &[Segment], html: bool)
+                   -> (Vec<Segment>, Vec<String>) {
+        let mut broken = vec![];
+        let resolved = segments.iter().map(|segment| {
+            let resolve_text = |text: &str, broken: &mut Vec<String>| {
+                // wiki links first would produce hrefs looking like broken relative links
+                let text = self.resolve_relative_links(source, root, text, html, broken);
+                self.resolve_wiki_links(source, root, &text, html, broken)
+            };
+            match segment {
+                &Segment::Title((level, ref text)) => Segment::Title((level, resolve_text(text, &mut broken))),
+                &Segment::Doc(ref text) => Segment::Doc(resolve_text(text, &mut broken)),
+                code => code.clone()
+            }
+        }).collect();
+        (resolved, broken)
+    }
+}
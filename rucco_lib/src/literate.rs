@@ -0,0 +1,184 @@
+//! Literate files: the source is markdown prose and its code blocks are the
+//! code, the other way around from commented sources.
+//!
+//! - markdown (`.litcoffee`, `.rs.md`...): fenced blocks, and indented blocks
+//!   following a blank line, are code. Fenced blocks tagged with another
+//!   language than the file's (```` ```sh ````) are examples, left in the doc
+//! - bird (`.lhs`): lines starting with `>` are code, as are lines between
+//!   `\begin{code}` and `\end{code}`
+
+use std;
+use regex::Regex;
+use segment::{title_or_doc_segment, Segment};
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Literate {
+    Markdown,
+    Bird
+}
+
+/// A line of a literate file, code being stripped of its block markup.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Line<'t> {
+    Doc(&'t str),
+    Code(&'t str),
+    /// line of a fenced block in another language, fences included: doc
+    Example(&'t str),
+    /// blank line outside of a fenced block
+    Blank,
+    /// fence or `\begin{code}`/`\end{code}`: ends the current code block
+    Fence
+}
+
+lazy_static! {
+    static ref FENCE_RE: Regex =
+        Regex::new(r"^ {0,3}(`{3,}|~{3,})([^`]*)$")
+        .expect("Wrong fence regexp");
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// "    code" or "\tcode" -> "code"
+fn unindent(line: &str) -> Option<&str> {
+    if line.starts_with('\t') {
+        Some(&line[1..])
+    } else if line.starts_with("    ") {
+        Some(&line[4..])
+    } else {
+        None
+    }
+}
+
+/// Whether a fence info string ("rust,ignore") tags code of the file, whose
+/// language goes by `tags` (any language if none).
+fn is_code_fence(info: &str, tags: &[String]) -> bool {
+    let tag = info.split(|c: char| c == ',' || c.is_whitespace()).next().unwrap_or("");
+    tag.is_empty() || tags.is_empty() || tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+}
+
+fn markdown_lines<'t>(source: &'t str, tags: &[String]) -> Vec<Line<'t>> {
+    let mut lines = vec![];
+    // fence char and length, and whether it is an example
+    let mut fence: Option<(char, usize, bool)> = None;
+    let mut indented = false;
+    let mut after_blank = true;
+    for line in source.lines() {
+        let line = line.trim_end_matches('\r');
+        if let Some((fence_char, fence_len, example)) = fence {
+            let closing = FENCE_RE.captures(line).map_or(false, |c| {
+                c[1].starts_with(fence_char) && c[1].len() >= fence_len && is_blank(&c[2])
+            });
+            if closing {
+                fence = None;
+                after_blank = false;
+            }
+            lines.push(match (example, closing) {
+                (true, _) => Line::Example(line),
+                (false, true) => Line::Fence,
+                (false, false) => Line::Code(line)
+            });
+            continue;
+        }
+        if let Some(c) = FENCE_RE.captures(line) {
+            let example = !is_code_fence(c[2].trim(), tags);
+            fence = c[1].chars().next().map(|fence_char| (fence_char, c[1].len(), example));
+            indented = false;
+            lines.push(if example { Line::Example(line) } else { Line::Fence });
+            continue;
+        }
+        if is_blank(line) {
+            lines.push(Line::Blank);
+            after_blank = true;
+            continue;
+        }
+        match unindent(line) {
+            Some(code) if indented || after_blank => {
+                indented = true;
+                lines.push(Line::Code(code));
+            },
+            _ => {
+                indented = false;
+                lines.push(Line::Doc(line));
+            }
+        }
+        after_blank = false;
+    }
+    lines
+}
+
+fn bird_lines<'t>(source: &'t str) -> Vec<Line<'t>> {
+    let mut lines = vec![];
+    let mut in_code = false;
+    for line in source.lines() {
+        let line = line.trim_end_matches('\r');
+        if in_code {
+            if line.trim() == r"\end{code}" {
+                in_code = false;
+                lines.push(Line::Fence);
+            } else {
+                lines.push(Line::Code(line));
+            }
+        } else if line.trim() == r"\begin{code}" {
+            in_code = true;
+            lines.push(Line::Fence);
+        } else if line.starts_with('>') {
+            let code = &line[1..];
+            lines.push(Line::Code(if code.starts_with(' ') { &code[1..] } else { code }));
+        } else if is_blank(line) {
+            lines.push(Line::Blank);
+        } else {
+            lines.push(Line::Doc(line));
+        }
+    }
+    lines
+}
+
+/// `tags` name the language of the code (`Syntax::fence_tags`).
+pub fn literate_lines<'t>(literate: Literate, tags: &[String], source: &'t str) -> Vec<Line<'t>> {
+    match literate {
+        Literate::Markdown => markdown_lines(source, tags),
+        Literate::Bird => bird_lines(source)
+    }
+}
+
+/// Segments of a literate file, one per line (to be compacted). Blank lines
+/// are blank code, so that the compaction keeps them in docs as paragraph
+/// breaks.
+pub fn literate_segments(literate: Literate, tags: &[String], source: &str) -> Vec<Segment> {
+    literate_lines(literate, tags, source).into_iter().zip(1..).filter_map(|(line, number)| match line {
+        Line::Doc(doc) => Some(title_or_doc_segment(&[doc, "\n"].concat())),
+        Line::Example(example) => Some(Segment::Doc([example, "\n"].concat())),
+        Line::Code(code) => Some(Segment::Code(([code, "\n"].concat(), vec![], vec![number]))),
+        Line::Blank => Some(Segment::Code(("\n".to_owned(), vec![], vec![number]))),
+        Line::Fence => None
+    }).collect()
+}
+
+/// The code blocks of a literate file, blank lines within blocks included.
+/// Examples in other languages than `tags` are left out.
+pub fn code_blocks<'t>(literate: Literate, tags: &[String], source: &'t str) -> Vec<Vec<&'t str>> {
+    let mut blocks = vec![];
+    let mut block: Vec<&str> = vec![];
+    for line in literate_lines(literate, tags, source) {
+        match line {
+            Line::Code(code) => block.push(code),
+            Line::Blank if !block.is_empty() => block.push(""),
+            Line::Blank => {},
+            Line::Doc(_) | Line::Example(_) | Line::Fence => if !block.is_empty() {
+                blocks.push(std::mem::replace(&mut block, vec![]));
+            }
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+    for block in &mut blocks {
+        while block.last().map_or(false, |l| is_blank(l)) {
+            block.pop();
+        }
+    }
+    blocks.retain(|block| !block.is_empty());
+    blocks
+}
@@ -0,0 +1,225 @@
+//! Markdown rendering of docs: CommonMark, plus the extensions listed in the
+//! ruccofile.
+//!
+//! ```toml
+//! [markdown]
+//! extensions = ["tables", "footnotes", "strikethrough", "tasklists", "autolinks", "heading-ids"]
+//! ```
+//!
+//! - `tables`, `footnotes`, `strikethrough`, `tasklists`: as on github
+//! - `autolinks`: bare `http://`, `https://` and `www.` urls become links
+//! - `heading-ids`: `# Title {#id .class}` sets the heading attributes
+//! - `smart-punctuation`: curly quotes, dashes and ellipses
+//! - `math`: `$...$` and `$$...$$` LaTeX, rendered to MathML (see `math`)
+//!
+//! Code blocks can be handed over to a highlighter (`render_with`), with the
+//! language of their fence if any.
+
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag, TagEnd, TextMergeStream};
+use regex::Regex;
+
+use math;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Extension {
+    Tables,
+    Footnotes,
+    Strikethrough,
+    Tasklists,
+    Autolinks,
+    HeadingIds,
+    SmartPunctuation,
+    Math
+}
+
+/// The github flavor, the default.
+pub const GFM_EXTENSIONS: [Extension; 6] = [Extension::Tables, Extension::Footnotes,
+                                            Extension::Strikethrough, Extension::Tasklists,
+                                            Extension::Autolinks, Extension::HeadingIds];
+
+impl Extension {
+    pub fn from_name(name: &str) -> Option<Extension> {
+        match name {
+            "tables" => Some(Extension::Tables),
+            "footnotes" => Some(Extension::Footnotes),
+            "strikethrough" => Some(Extension::Strikethrough),
+            "tasklists" => Some(Extension::Tasklists),
+            "autolinks" => Some(Extension::Autolinks),
+            "heading-ids" => Some(Extension::HeadingIds),
+            "smart-punctuation" => Some(Extension::SmartPunctuation),
+            "math" => Some(Extension::Math),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Extension::Tables => "tables",
+            Extension::Footnotes => "footnotes",
+            Extension::Strikethrough => "strikethrough",
+            Extension::Tasklists => "tasklists",
+            Extension::Autolinks => "autolinks",
+            Extension::HeadingIds => "heading-ids",
+            Extension::SmartPunctuation => "smart-punctuation",
+            Extension::Math => "math"
+        }
+    }
+
+    fn option(&self) -> Options {
+        match *self {
+            Extension::Tables => Options::ENABLE_TABLES,
+            Extension::Footnotes => Options::ENABLE_FOOTNOTES,
+            Extension::Strikethrough => Options::ENABLE_STRIKETHROUGH,
+            Extension::Tasklists => Options::ENABLE_TASKLISTS,
+            Extension::HeadingIds => Options::ENABLE_HEADING_ATTRIBUTES,
+            Extension::SmartPunctuation => Options::ENABLE_SMART_PUNCTUATION,
+            Extension::Math => Options::ENABLE_MATH,
+            Extension::Autolinks => Options::empty() // done here
+        }
+    }
+}
+
+lazy_static! {
+    static ref URL_RE: Regex =
+        Regex::new(r#"\b(?:https?://|www\.)[^\s<]*[^\s<.,:;!?'")\]*_~]"#)
+        .expect("Wrong url regexp");
+}
+
+pub struct Markdown {
+    extensions: Vec<Extension>
+}
+
+impl Markdown {
+    pub fn new(extensions: &[Extension]) -> Markdown {
+        Markdown { extensions: extensions.to_vec() }
+    }
+
+    pub fn defaults() -> Markdown {
+        Markdown::new(&GFM_EXTENSIONS)
+    }
+
+    pub fn has(&self, extension: Extension) -> bool {
+        self.extensions.contains(&extension)
+    }
+
+    pub fn render(&self, text: &str) -> String {
+        self.render_with(text, &|_, _| None)
+    }
+
+    /// Renders code blocks with `code_block(language, code)`, given the first
+    /// word of their fence (`rust` for "```rust,ignore") or `None` if untagged.
+    /// Blocks it returns `None` for are left to the markdown renderer.
+    pub fn render_with(&self, text: &str, code_block: &dyn Fn(Option<&str>, &str) -> Option<String>) -> String {
+        let options = self.extensions.iter().fold(Options::empty(), |o, e| o | e.option());
+        let events = TextMergeStream::new(Parser::new_ext(text, options)).map(|event| match event {
+            Event::InlineMath(tex) => Event::InlineHtml(CowStr::from(math::to_mathml(&tex, false))),
+            Event::DisplayMath(tex) => Event::InlineHtml(CowStr::from(math::to_mathml(&tex, true))),
+            event => event
+        });
+        let events = if self.has(Extension::Autolinks) {
+            autolinks(events)
+        } else {
+            events.collect()
+        };
+        let mut html_output = String::with_capacity(text.len() * 3 / 2);
+        html::push_html(&mut html_output, code_blocks(events, code_block).into_iter());
+        html_output
+    }
+}
+
+/// Replaces the code blocks `code_block` renders by its html.
+fn code_blocks<'a>(events: Vec<Event<'a>>, code_block: &dyn Fn(Option<&str>, &str) -> Option<String>) -> Vec<Event<'a>> {
+    let mut rendered = Vec::with_capacity(events.len());
+    let mut block: Option<Vec<Event<'a>>> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => block = Some(vec![event]),
+            Event::End(TagEnd::CodeBlock) => {
+                let mut events = block.take().unwrap_or_default();
+                let code: String = events.iter()
+                    .filter_map(|e| match *e { Event::Text(ref text) => Some(text.as_ref()), _ => None })
+                    .collect();
+                let html = match events.first() {
+                    Some(&Event::Start(Tag::CodeBlock(ref kind))) => code_block(fence_language(kind), &code),
+                    _ => None
+                };
+                match html {
+                    Some(html) => rendered.push(Event::Html(CowStr::from(html))),
+                    None => {
+                        events.push(event);
+                        rendered.extend(events);
+                    }
+                }
+            },
+            event => match block {
+                Some(ref mut events) => events.push(event),
+                None => rendered.push(event)
+            }
+        }
+    }
+    rendered
+}
+
+/// `rust` for "```rust,no_run" (or "``` rust {.numbered}"), `None` for
+/// untagged and indented blocks.
+fn fence_language<'k>(kind: &'k CodeBlockKind) -> Option<&'k str> {
+    match *kind {
+        CodeBlockKind::Fenced(ref info) =>
+            info.split(|c: char| c == ',' || c.is_whitespace()).next().filter(|l| !l.is_empty()),
+        CodeBlockKind::Indented => None
+    }
+}
+
+/// Bare urls in text (but in links and code blocks) become links.
+fn autolinks<'a, I: Iterator<Item=Event<'a>>>(events: I) -> Vec<Event<'a>> {
+    let mut linked = vec![];
+    let mut link_depth = 0;
+    let mut in_code_block = false;
+    for event in events {
+        let text = match event {
+            Event::Start(Tag::Link { .. }) | Event::Start(Tag::Image { .. }) => {
+                link_depth += 1;
+                linked.push(event);
+                continue;
+            },
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                link_depth -= 1;
+                linked.push(event);
+                continue;
+            },
+            Event::Start(Tag::CodeBlock(_)) | Event::End(TagEnd::CodeBlock) => {
+                in_code_block = !in_code_block;
+                linked.push(event);
+                continue;
+            },
+            Event::Text(ref text) if link_depth == 0 && !in_code_block && URL_RE.is_match(text) =>
+                text.to_string(),
+            event => {
+                linked.push(event);
+                continue;
+            }
+        };
+        let mut last = 0;
+        for url in URL_RE.find_iter(&text) {
+            if url.start() > last {
+                linked.push(Event::Text(CowStr::from(text[last..url.start()].to_owned())));
+            }
+            let dest_url = if url.as_str().starts_with("www.") {
+                ["http://", url.as_str()].concat()
+            } else {
+                url.as_str().to_owned()
+            };
+            linked.push(Event::Start(Tag::Link { link_type: LinkType::Autolink,
+                                                 dest_url: CowStr::from(dest_url),
+                                                 title: CowStr::from(""),
+                                                 id: CowStr::from("") }));
+            linked.push(Event::Text(CowStr::from(url.as_str().to_owned())));
+            linked.push(Event::End(TagEnd::Link));
+            last = url.end();
+        }
+        if last < text.len() {
+            linked.push(Event::Text(CowStr::from(text[last..].to_owned())));
+        }
+    }
+    linked
+}
@@ -0,0 +1,629 @@
+//! LaTeX math in docs (`$...$` inline and `$$...$$` displayed, with the `math`
+//! markdown extension), rendered to MathML at build time: browsers lay it out
+//! natively, there is no script to run.
+//!
+//! The usual subset of LaTeX is understood: letters, numbers and operators,
+//! `^`, `_` and primes, `\frac`, `\sqrt`, `\binom`, greek letters and symbols,
+//! big operators (`\sum`, `\int`...), function names (`\sin`, `\lim`...),
+//! `\left` and `\right`, accents (`\hat`, `\vec`...), fonts (`\mathbf`,
+//! `\mathbb`, `\text`...) and the matrix, cases and aligned environments.
+//! Anything else (an unknown command, an unmatched `\left` or `\right`) shows
+//! as is, as an error in the formula; the whole source is kept in the formula's
+//! annotation. So does a formula nested too deep (`MAX_DEPTH`). An unterminated
+//! `$` is no math: it stays in the text.
+//!
+//! The layouts ship a math font (DejaVu Math TeX Gyre, in `public/fonts`) that
+//! their stylesheets use first, the reader's math font being the fallback.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Kind {
+    /// a variable: `<mi>`
+    Ident,
+    /// an upright identifier (capital greek)
+    Upright,
+    Op,
+    /// limits under and over it in displayed math: `\sum`
+    BigOp,
+    /// limits beside it: `\int`
+    Integral,
+    /// an upright function name: `\sin`
+    Func,
+    /// a function name with limits under it in displayed math: `\lim`
+    LimFunc,
+    /// styling left to the browser: `\displaystyle`
+    Ignored
+}
+
+const SYMBOLS: &'static [(&'static str, &'static str, Kind)] = &[
+    ("alpha", "α", Kind::Ident), ("beta", "β", Kind::Ident), ("gamma", "γ", Kind::Ident),
+    ("delta", "δ", Kind::Ident), ("epsilon", "ϵ", Kind::Ident), ("varepsilon", "ε", Kind::Ident),
+    ("zeta", "ζ", Kind::Ident), ("eta", "η", Kind::Ident), ("theta", "θ", Kind::Ident),
+    ("vartheta", "ϑ", Kind::Ident), ("iota", "ι", Kind::Ident), ("kappa", "κ", Kind::Ident),
+    ("lambda", "λ", Kind::Ident), ("mu", "μ", Kind::Ident), ("nu", "ν", Kind::Ident),
+    ("xi", "ξ", Kind::Ident), ("pi", "π", Kind::Ident), ("varpi", "ϖ", Kind::Ident),
+    ("rho", "ρ", Kind::Ident), ("varrho", "ϱ", Kind::Ident), ("sigma", "σ", Kind::Ident),
+    ("varsigma", "ς", Kind::Ident), ("tau", "τ", Kind::Ident), ("upsilon", "υ", Kind::Ident),
+    ("phi", "ϕ", Kind::Ident), ("varphi", "φ", Kind::Ident), ("chi", "χ", Kind::Ident),
+    ("psi", "ψ", Kind::Ident), ("omega", "ω", Kind::Ident),
+    ("Gamma", "Γ", Kind::Upright), ("Delta", "Δ", Kind::Upright), ("Theta", "Θ", Kind::Upright),
+    ("Lambda", "Λ", Kind::Upright), ("Xi", "Ξ", Kind::Upright), ("Pi", "Π", Kind::Upright),
+    ("Sigma", "Σ", Kind::Upright), ("Upsilon", "Υ", Kind::Upright), ("Phi", "Φ", Kind::Upright),
+    ("Psi", "Ψ", Kind::Upright), ("Omega", "Ω", Kind::Upright),
+    ("infty", "∞", Kind::Ident), ("partial", "∂", Kind::Ident), ("nabla", "∇", Kind::Ident),
+    ("ell", "ℓ", Kind::Ident), ("hbar", "ℏ", Kind::Ident), ("aleph", "ℵ", Kind::Ident),
+    ("Re", "ℜ", Kind::Ident), ("Im", "ℑ", Kind::Ident), ("emptyset", "∅", Kind::Ident),
+    ("varnothing", "∅", Kind::Ident), ("$", "$", Kind::Ident), ("#", "#", Kind::Ident),
+    ("_", "_", Kind::Ident),
+    ("pm", "±", Kind::Op), ("mp", "∓", Kind::Op), ("times", "×", Kind::Op), ("div", "÷", Kind::Op),
+    ("cdot", "⋅", Kind::Op), ("ast", "∗", Kind::Op), ("star", "⋆", Kind::Op), ("circ", "∘", Kind::Op),
+    ("bullet", "∙", Kind::Op), ("oplus", "⊕", Kind::Op), ("ominus", "⊖", Kind::Op),
+    ("otimes", "⊗", Kind::Op), ("odot", "⊙", Kind::Op), ("cup", "∪", Kind::Op), ("cap", "∩", Kind::Op),
+    ("setminus", "∖", Kind::Op), ("wedge", "∧", Kind::Op), ("land", "∧", Kind::Op),
+    ("vee", "∨", Kind::Op), ("lor", "∨", Kind::Op), ("neg", "¬", Kind::Op), ("lnot", "¬", Kind::Op),
+    ("leq", "≤", Kind::Op), ("le", "≤", Kind::Op), ("geq", "≥", Kind::Op), ("ge", "≥", Kind::Op),
+    ("neq", "≠", Kind::Op), ("ne", "≠", Kind::Op), ("ll", "≪", Kind::Op), ("gg", "≫", Kind::Op),
+    ("approx", "≈", Kind::Op), ("equiv", "≡", Kind::Op), ("sim", "∼", Kind::Op),
+    ("simeq", "≃", Kind::Op), ("cong", "≅", Kind::Op), ("propto", "∝", Kind::Op),
+    ("in", "∈", Kind::Op), ("notin", "∉", Kind::Op), ("ni", "∋", Kind::Op),
+    ("subset", "⊂", Kind::Op), ("subseteq", "⊆", Kind::Op), ("supset", "⊃", Kind::Op),
+    ("supseteq", "⊇", Kind::Op), ("mid", "∣", Kind::Op), ("parallel", "∥", Kind::Op),
+    ("perp", "⊥", Kind::Op), ("angle", "∠", Kind::Op), ("forall", "∀", Kind::Op),
+    ("exists", "∃", Kind::Op), ("to", "→", Kind::Op), ("rightarrow", "→", Kind::Op),
+    ("leftarrow", "←", Kind::Op), ("gets", "←", Kind::Op), ("leftrightarrow", "↔", Kind::Op),
+    ("Rightarrow", "⇒", Kind::Op), ("Leftarrow", "⇐", Kind::Op), ("Leftrightarrow", "⇔", Kind::Op),
+    ("implies", "⟹", Kind::Op), ("iff", "⟺", Kind::Op), ("mapsto", "↦", Kind::Op),
+    ("uparrow", "↑", Kind::Op), ("downarrow", "↓", Kind::Op),
+    ("ldots", "…", Kind::Op), ("dots", "…", Kind::Op), ("cdots", "⋯", Kind::Op),
+    ("vdots", "⋮", Kind::Op), ("ddots", "⋱", Kind::Op), ("prime", "′", Kind::Op),
+    ("langle", "⟨", Kind::Op), ("rangle", "⟩", Kind::Op), ("lfloor", "⌊", Kind::Op),
+    ("rfloor", "⌋", Kind::Op), ("lceil", "⌈", Kind::Op), ("rceil", "⌉", Kind::Op),
+    ("vert", "|", Kind::Op), ("lvert", "|", Kind::Op), ("rvert", "|", Kind::Op),
+    ("Vert", "‖", Kind::Op), ("lVert", "‖", Kind::Op), ("rVert", "‖", Kind::Op),
+    ("|", "‖", Kind::Op), ("{", "{", Kind::Op), ("}", "}", Kind::Op), ("lbrace", "{", Kind::Op),
+    ("rbrace", "}", Kind::Op), ("%", "%", Kind::Op), ("&", "&", Kind::Op), ("colon", ":", Kind::Op),
+    ("sum", "∑", Kind::BigOp), ("prod", "∏", Kind::BigOp), ("coprod", "∐", Kind::BigOp),
+    ("bigcup", "⋃", Kind::BigOp), ("bigcap", "⋂", Kind::BigOp), ("bigoplus", "⨁", Kind::BigOp),
+    ("bigotimes", "⨂", Kind::BigOp), ("bigvee", "⋁", Kind::BigOp), ("bigwedge", "⋀", Kind::BigOp),
+    ("int", "∫", Kind::Integral), ("iint", "∬", Kind::Integral), ("iiint", "∭", Kind::Integral),
+    ("oint", "∮", Kind::Integral),
+    ("sin", "sin", Kind::Func), ("cos", "cos", Kind::Func), ("tan", "tan", Kind::Func),
+    ("cot", "cot", Kind::Func), ("sec", "sec", Kind::Func), ("csc", "csc", Kind::Func),
+    ("arcsin", "arcsin", Kind::Func), ("arccos", "arccos", Kind::Func), ("arctan", "arctan", Kind::Func),
+    ("sinh", "sinh", Kind::Func), ("cosh", "cosh", Kind::Func), ("tanh", "tanh", Kind::Func),
+    ("log", "log", Kind::Func), ("ln", "ln", Kind::Func), ("lg", "lg", Kind::Func),
+    ("exp", "exp", Kind::Func), ("dim", "dim", Kind::Func), ("ker", "ker", Kind::Func),
+    ("deg", "deg", Kind::Func), ("arg", "arg", Kind::Func), ("hom", "hom", Kind::Func),
+    ("lim", "lim", Kind::LimFunc), ("liminf", "lim inf", Kind::LimFunc),
+    ("limsup", "lim sup", Kind::LimFunc), ("max", "max", Kind::LimFunc), ("min", "min", Kind::LimFunc),
+    ("sup", "sup", Kind::LimFunc), ("inf", "inf", Kind::LimFunc), ("det", "det", Kind::LimFunc),
+    ("gcd", "gcd", Kind::LimFunc), ("Pr", "Pr", Kind::LimFunc),
+    ("displaystyle", "", Kind::Ignored), ("textstyle", "", Kind::Ignored)
+];
+
+/// Accents over their argument, and whether they stretch.
+const ACCENTS: &'static [(&'static str, &'static str, bool)] = &[
+    ("hat", "^", false), ("widehat", "^", true), ("check", "ˇ", false), ("tilde", "~", false),
+    ("widetilde", "~", true), ("bar", "¯", false), ("overline", "‾", true), ("vec", "→", false),
+    ("overrightarrow", "→", true), ("overleftarrow", "←", true), ("dot", "˙", false),
+    ("ddot", "¨", false), ("breve", "˘", false), ("acute", "´", false), ("grave", "`", false)
+];
+
+/// Horizontal spaces, in em.
+const SPACES: &'static [(&'static str, &'static str)] = &[
+    (",", "0.1667em"), ("thinspace", "0.1667em"), (":", "0.2222em"), (">", "0.2222em"),
+    ("medspace", "0.2222em"), (";", "0.2778em"), ("thickspace", "0.2778em"), (" ", "0.3333em"),
+    ("quad", "1em"), ("qquad", "2em"), ("!", "-0.1667em")
+];
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Font {
+    Upright,
+    Bold,
+    Italic,
+    BoldItalic,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace
+}
+
+const FONTS: &'static [(&'static str, Font)] = &[
+    ("mathrm", Font::Upright), ("operatorname", Font::Upright), ("mathbf", Font::Bold),
+    ("mathit", Font::Italic), ("boldsymbol", Font::BoldItalic), ("bm", Font::BoldItalic),
+    ("mathcal", Font::Script), ("mathscr", Font::Script), ("mathfrak", Font::Fraktur),
+    ("mathbb", Font::DoubleStruck), ("mathsf", Font::SansSerif), ("mathtt", Font::Monospace)
+];
+
+/// Letters the mathematical alphanumeric symbols leave out, as they were
+/// already in the letterlike symbols.
+const LETTERLIKE: &'static [(Font, char, char)] = &[
+    (Font::Italic, 'h', 'ℎ'),
+    (Font::Script, 'B', 'ℬ'), (Font::Script, 'E', 'ℰ'), (Font::Script, 'F', 'ℱ'),
+    (Font::Script, 'H', 'ℋ'), (Font::Script, 'I', 'ℐ'), (Font::Script, 'L', 'ℒ'),
+    (Font::Script, 'M', 'ℳ'), (Font::Script, 'R', 'ℛ'), (Font::Script, 'e', 'ℯ'),
+    (Font::Script, 'g', 'ℊ'), (Font::Script, 'o', 'ℴ'),
+    (Font::Fraktur, 'C', 'ℭ'), (Font::Fraktur, 'H', 'ℌ'), (Font::Fraktur, 'I', 'ℑ'),
+    (Font::Fraktur, 'R', 'ℜ'), (Font::Fraktur, 'Z', 'ℨ'),
+    (Font::DoubleStruck, 'C', 'ℂ'), (Font::DoubleStruck, 'H', 'ℍ'), (Font::DoubleStruck, 'N', 'ℕ'),
+    (Font::DoubleStruck, 'P', 'ℙ'), (Font::DoubleStruck, 'Q', 'ℚ'), (Font::DoubleStruck, 'R', 'ℝ'),
+    (Font::DoubleStruck, 'Z', 'ℤ')
+];
+
+impl Font {
+    /// First capital, small letter and digit of the font in the mathematical
+    /// alphanumeric symbols.
+    fn alphanumerics(&self) -> Option<(u32, u32, Option<u32>)> {
+        match *self {
+            Font::Upright => None,
+            Font::Bold => Some((0x1D400, 0x1D41A, Some(0x1D7CE))),
+            Font::Italic => Some((0x1D434, 0x1D44E, None)),
+            Font::BoldItalic => Some((0x1D468, 0x1D482, None)),
+            Font::Script => Some((0x1D49C, 0x1D4B6, None)),
+            Font::Fraktur => Some((0x1D504, 0x1D51E, None)),
+            Font::DoubleStruck => Some((0x1D538, 0x1D552, Some(0x1D7D8))),
+            Font::SansSerif => Some((0x1D5A0, 0x1D5BA, Some(0x1D7E2))),
+            Font::Monospace => Some((0x1D670, 0x1D68A, Some(0x1D7F6)))
+        }
+    }
+
+    /// `c` in this font, if it has it.
+    fn style(&self, c: char) -> char {
+        if let Some(&(_, _, letterlike)) = LETTERLIKE.iter().find(|&&(f, l, _)| f == *self && l == c) {
+            return letterlike;
+        }
+        let styled = self.alphanumerics().and_then(|(capitals, smalls, digits)| match c {
+            'A'..='Z' => Some(capitals + (c as u32 - 'A' as u32)),
+            'a'..='z' => Some(smalls + (c as u32 - 'a' as u32)),
+            '0'..='9' => digits.map(|d| d + (c as u32 - '0' as u32)),
+            _ => None
+        });
+        styled.and_then(::std::char::from_u32).unwrap_or(c)
+    }
+}
+
+/// The MathML of a LaTeX formula, `display`ed as a block or inline.
+pub fn to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = Parser::new(tex, 0);
+    let mut nodes = parser.row();
+    // stray closing brace, &, \\, \right or \end
+    while let Some(c) = parser.peek() {
+        let start = parser.pos;
+        let name = parser.command_name();
+        parser.pos += match name {
+            Some(name) => 1 + name.len(),
+            None => c.len_utf8()
+        };
+        if name == Some("right") {
+            parser.delimiter(); // shown with its `\right`
+        }
+        nodes.push(error(&tex[start..parser.pos]));
+        nodes.extend(parser.row());
+    }
+    if parser.too_deep {
+        nodes = vec![error(tex.trim())];
+    }
+    format!("<math{}><semantics>{}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+            if display { " display=\"block\"" } else { "" }, mrow(nodes), escape(tex.trim()))
+}
+
+/// Deepest nesting of groups and commands, past which the whole formula
+/// shows as is.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'t> {
+    tex: &'t str,
+    pos: usize,
+    /// nesting of the atom being parsed
+    depth: usize,
+    too_deep: bool
+}
+
+impl<'t> Parser<'t> {
+    fn new(tex: &'t str, depth: usize) -> Parser<'t> {
+        Parser { tex: tex, pos: 0, depth: depth, too_deep: false }
+    }
+
+    fn rest(&self) -> &'t str {
+        &self.tex[self.pos..]
+    }
+
+    /// Next char, past spaces.
+    fn peek(&mut self) -> Option<char> {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+        self.rest().chars().next()
+    }
+
+    /// Name of the command at `pos` (letters, or a single other char).
+    fn command_name(&self) -> Option<&'t str> {
+        let rest = self.rest();
+        if !rest.starts_with('\\') {
+            return None;
+        }
+        let name = &rest[1..];
+        let letters = name.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(name.len());
+        match name.chars().next() {
+            Some(c) if letters == 0 => Some(&name[..c.len_utf8()]),
+            Some(_) => Some(&name[..letters]),
+            None => None
+        }
+    }
+
+    /// Nodes up to the end of the group, cell, row or environment.
+    fn row(&mut self) -> Vec<String> {
+        let mut nodes = vec![];
+        while let Some(node) = self.scripted() {
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    /// An atom and its scripts (limits of big operators in displayed math).
+    fn scripted(&mut self) -> Option<String> {
+        let (base, mut limits) = self.atom()?;
+        let (mut sub, mut sup) = (None, None);
+        loop {
+            match self.peek() {
+                Some('\\') if matches!(self.command_name(), Some("limits") | Some("nolimits")) => {
+                    limits = self.command_name() == Some("limits");
+                    self.pos += 1 + self.command_name().map_or(0, str::len);
+                },
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.arg());
+                },
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.arg());
+                },
+                Some('\'') if sup.is_none() => {
+                    let primes = self.rest().len() - self.rest().trim_start_matches('\'').len();
+                    self.pos += primes;
+                    sup = Some(format!("<mo>{}</mo>", "′".repeat(primes)));
+                },
+                _ => break
+            }
+        }
+        let (under, over, both) = if limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        Some(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base, sup),
+            (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base, sub, sup)
+        })
+    }
+
+    /// A node, and whether its scripts are limits. None at the end of a row,
+    /// and of the formula when nested too deep.
+    fn atom(&mut self) -> Option<(String, bool)> {
+        if self.depth >= MAX_DEPTH {
+            self.too_deep = true;
+            self.pos = self.tex.len();
+            return None;
+        }
+        self.depth += 1;
+        let atom = self.nested_atom();
+        self.depth -= 1;
+        atom
+    }
+
+    fn nested_atom(&mut self) -> Option<(String, bool)> {
+        let c = self.peek()?;
+        let node = match c {
+            '}' | '&' => return None,
+            '\\' => return self.command(),
+            '{' => {
+                self.pos += 1;
+                let nodes = self.row();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                }
+                mrow(nodes)
+            },
+            '^' | '_' => mrow(vec![]), // nothing to script
+            '0'..='9' | '.' => {
+                let rest = self.rest();
+                let mut len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                if rest[len..].starts_with('.') && rest[len + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+                    len += 1 + rest[len + 1..].find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len() - len - 1);
+                }
+                let len = len.max(1);
+                self.pos += len;
+                if &rest[..len] == "." {
+                    "<mo>.</mo>".to_string()
+                } else {
+                    format!("<mn>{}</mn>", &rest[..len])
+                }
+            },
+            '~' => {
+                self.pos += 1;
+                "<mspace width=\"0.3333em\"></mspace>".to_string()
+            },
+            c => {
+                self.pos += c.len_utf8();
+                if c.is_alphabetic() {
+                    format!("<mi>{}</mi>", escape(&c.to_string()))
+                } else {
+                    mo(match c {
+                        '-' => "−".to_string(),
+                        '*' => "∗".to_string(),
+                        c => c.to_string()
+                    }.as_str())
+                }
+            }
+        };
+        Some((node, false))
+    }
+
+    /// The braced argument of a command, or its next char (`\frac12`).
+    fn arg(&mut self) -> String {
+        if let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            self.pos += 1;
+            return format!("<mn>{}</mn>", c);
+        }
+        match self.atom() {
+            Some((node, _)) => node,
+            None => error("missing argument")
+        }
+    }
+
+    /// The braced argument of a command as is, or its next char.
+    fn raw_arg(&mut self) -> &'t str {
+        let start = self.pos;
+        match self.peek() {
+            Some('{') => {
+                let mut depth = 0;
+                for (i, c) in self.rest().char_indices() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 1 => {
+                            let arg = &self.rest()[1..i];
+                            self.pos += i + 1;
+                            return arg;
+                        },
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                }
+                let arg = &self.rest()[1..];
+                self.pos = self.tex.len();
+                arg
+            },
+            Some(c) => {
+                self.pos += c.len_utf8();
+                &self.tex[self.pos - c.len_utf8()..self.pos]
+            },
+            None => &self.tex[start..start]
+        }
+    }
+
+    /// `\left`/`\right` delimiter, none for `.`.
+    fn delimiter(&mut self) -> String {
+        self.peek();
+        if let Some(name) = self.command_name() {
+            self.pos += 1 + name.len();
+            return match SYMBOLS.iter().find(|&&(n, _, _)| n == name) {
+                Some(&(_, symbol, _)) => fence(symbol),
+                None => error(&["\\", name].concat())
+            };
+        }
+        match self.peek() {
+            Some('.') | None => {
+                self.pos = (self.pos + 1).min(self.tex.len());
+                String::new()
+            },
+            Some(c) => {
+                self.pos += c.len_utf8();
+                fence(&c.to_string())
+            }
+        }
+    }
+
+    fn command(&mut self) -> Option<(String, bool)> {
+        let name = self.command_name().unwrap_or("");
+        if name == "\\" || name == "right" || name == "end" {
+            return None;
+        }
+        self.pos += 1 + name.len();
+        let node = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.arg();
+                format!("<mfrac>{}{}</mfrac>", numerator, self.arg())
+            },
+            "binom" => {
+                let n = self.arg();
+                format!("<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>", n, self.arg())
+            },
+            "sqrt" => {
+                if self.peek() == Some('[') {
+                    let end = self.rest().find(']').unwrap_or(self.rest().len());
+                    let index = self.to_row(&self.rest()[1..end]);
+                    self.pos = (self.pos + end + 1).min(self.tex.len());
+                    format!("<mroot>{}{}</mroot>", self.arg(), index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.arg())
+                }
+            },
+            "overset" | "stackrel" => {
+                let over = self.arg();
+                format!("<mover>{}{}</mover>", self.arg(), over)
+            },
+            "underset" => {
+                let under = self.arg();
+                format!("<munder>{}{}</munder>", self.arg(), under)
+            },
+            "left" => {
+                let start = self.pos - "\\left".len();
+                let mut nodes = vec![self.delimiter()];
+                let opened = self.pos;
+                nodes.extend(self.row());
+                if self.command_name() == Some("right") {
+                    self.pos += 1 + "right".len();
+                    nodes.push(self.delimiter());
+                } else {
+                    // unbalanced: the unmatched `\left(` shows as is
+                    nodes[0] = error(&self.tex[start..opened]);
+                }
+                mrow(nodes)
+            },
+            "begin" => self.environment(),
+            "text" | "textrm" | "textnormal" | "mbox" => format!("<mtext>{}</mtext>", escape(self.raw_arg())),
+            "underline" => format!("<munder accentunder=\"true\">{}<mo stretchy=\"true\">_</mo></munder>", self.arg()),
+            "underbrace" => return Some((format!("<munder>{}<mo stretchy=\"true\">⏟</mo></munder>", self.arg()), true)),
+            "overbrace" => return Some((format!("<mover>{}<mo stretchy=\"true\">⏞</mo></mover>", self.arg()), true)),
+            name => {
+                if let Some(&(_, accent, stretchy)) = ACCENTS.iter().find(|&&(n, _, _)| n == name) {
+                    format!("<mover accent=\"true\">{}<mo stretchy=\"{}\">{}</mo></mover>",
+                            self.arg(), stretchy, escape(accent))
+                } else if let Some(&(_, width)) = SPACES.iter().find(|&&(n, _)| n == name) {
+                    format!("<mspace width=\"{}\"></mspace>", width)
+                } else if let Some(&(_, font)) = FONTS.iter().find(|&&(n, _)| n == name) {
+                    let text = self.raw_arg();
+                    self.styled(text, font)
+                } else if let Some(&(_, symbol, kind)) = SYMBOLS.iter().find(|&&(n, _, _)| n == name) {
+                    return Some(symbol_node(symbol, kind));
+                } else {
+                    error(&["\\", name].concat())
+                }
+            }
+        };
+        Some((node, false))
+    }
+
+    /// `\begin{pmatrix} a & b \\ c & d \end{pmatrix}`, past `\begin`.
+    fn environment(&mut self) -> String {
+        let name = self.raw_arg();
+        if name == "array" {
+            self.raw_arg(); // column spec
+        }
+        let (open, close, columnalign) = match name {
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left left")),
+            "aligned" | "align" | "align*" | "split" => ("", "", Some("right left")),
+            "matrix" | "smallmatrix" | "array" | "gathered" | "gather" | "gather*" => ("", "", None),
+            _ => return error(&["\\begin{", name, "}"].concat())
+        };
+        let mut rows = vec![];
+        let mut cells = vec![];
+        loop {
+            cells.push(format!("<mtd>{}</mtd>", mrow(self.row())));
+            match (self.peek(), self.command_name()) {
+                (Some('&'), _) => self.pos += 1,
+                (_, Some("\\")) => {
+                    self.pos += 2;
+                    rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+                    cells.clear();
+                },
+                (_, Some("end")) => {
+                    self.pos += 1 + "end".len();
+                    self.raw_arg();
+                    break;
+                },
+                _ => break // unterminated
+            }
+        }
+        if cells.len() > 1 || cells.first().map_or(false, |cell| cell != "<mtd><mrow></mrow></mtd>") {
+            rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+        }
+        let table = match columnalign {
+            Some(align) => format!("<mtable columnalign=\"{}\">{}</mtable>", align, rows.concat()),
+            None => format!("<mtable>{}</mtable>", rows.concat())
+        };
+        let mut nodes = vec![];
+        if !open.is_empty() {
+            nodes.push(fence(open));
+        }
+        nodes.push(table);
+        if !close.is_empty() {
+            nodes.push(fence(close));
+        }
+        mrow(nodes)
+    }
+}
+
+fn symbol_node(symbol: &str, kind: Kind) -> (String, bool) {
+    match kind {
+        Kind::Ident => (format!("<mi>{}</mi>", symbol), false),
+        Kind::Upright => (format!("<mi mathvariant=\"normal\">{}</mi>", symbol), false),
+        Kind::Op => (mo(symbol), false),
+        Kind::BigOp => (format!("<mo largeop=\"true\" movablelimits=\"true\">{}</mo>", symbol), true),
+        Kind::Integral => (format!("<mo largeop=\"true\">{}</mo>", symbol), false),
+        Kind::Func => (format!("<mi>{}</mi>", symbol), false),
+        Kind::LimFunc => (format!("<mo movablelimits=\"true\" form=\"prefix\">{}</mo>", symbol), true),
+        Kind::Ignored => (String::new(), false)
+    }
+}
+
+impl<'t> Parser<'t> {
+    /// A formula inside another (`\sqrt[n+1]`), as deep.
+    fn to_row(&mut self, tex: &str) -> String {
+        let mut parser = Parser::new(tex, self.depth);
+        let row = mrow(parser.row());
+        if parser.too_deep {
+            self.too_deep = true;
+            self.pos = self.tex.len();
+        }
+        row
+    }
+
+    /// The letters and digits of `text` in a font. Formulas (`\mathbf{\alpha}`)
+    /// are left as they are.
+    fn styled(&mut self, text: &str, font: Font) -> String {
+        if text.contains('\\') {
+            return self.to_row(text);
+        }
+        if font == Font::Upright && text.chars().all(char::is_alphabetic) {
+            return if text.chars().count() == 1 {
+                format!("<mi mathvariant=\"normal\">{}</mi>", escape(text))
+            } else {
+                format!("<mi>{}</mi>", escape(text)) // several letters are upright anyway
+            };
+        }
+        let nodes = text.chars().filter(|c| !c.is_whitespace()).map(|c| {
+            if c.is_ascii_digit() {
+                format!("<mn>{}</mn>", font.style(c))
+            } else if c.is_alphabetic() && font == Font::Upright {
+                format!("<mi mathvariant=\"normal\">{}</mi>", c)
+            } else if c.is_alphabetic() {
+                format!("<mi>{}</mi>", font.style(c))
+            } else {
+                mo(&c.to_string())
+            }
+        }).collect();
+        mrow(nodes)
+    }
+}
+
+fn mrow(nodes: Vec<String>) -> String {
+    if nodes.len() == 1 {
+        nodes.into_iter().next().unwrap_or_default()
+    } else {
+        ["<mrow>", &nodes.concat(), "</mrow>"].concat()
+    }
+}
+
+fn mo(op: &str) -> String {
+    format!("<mo>{}</mo>", escape(op))
+}
+
+fn fence(delimiter: &str) -> String {
+    format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(delimiter))
+}
+
+fn error(tex: &str) -> String {
+    warn!("unsupported math: {}", tex);
+    format!("<merror><mtext>{}</mtext></merror>", escape(tex))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,117 @@
+//! The outline of a page, built from its `Segment::Title`s, and the anchors of
+//! its segments.
+//!
+//! Titles are anchored by a slug of their text, so that links to them survive
+//! segments being added or removed above. Other segments keep a positional
+//! `segment-N` anchor.
+
+use segment::Segment;
+use std::collections::HashSet;
+
+/// Default maximum heading level showing in the outline.
+pub const DEFAULT_DEPTH: u8 = 3;
+
+#[derive(Debug)]
+pub struct Heading {
+    pub level: u8,
+    pub title: String,
+    pub anchor: String,
+    pub children: Vec<Heading>
+}
+
+/// Rendered html -> plain text, whitespace collapsed.
+pub fn plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            },
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text.replace("&lt;", "<").replace("&gt;", ">")
+        .replace("&quot;", "\"").replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Text of a title, be it rendered ("<h2>Conf files</h2>") or raw ("## Conf files").
+pub fn title_text(title: &str) -> String {
+    plain_text(title).trim_start_matches('#').trim().to_owned()
+}
+
+/// "Conf files!" -> "conf-files"
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// One anchor per segment: the slug of titles, else the segment's position
+/// ("segment-3"). Positions are reserved first, and duplicate slugs get a
+/// "-1", "-2"... suffix.
+pub fn anchors(segments: &[Segment]) -> Vec<String> {
+    let slugs: Vec<String> = segments.iter().map(|segment| match segment {
+        &Segment::Title((_, ref title)) => slugify(&title_text(title)),
+        _ => String::new()
+    }).collect();
+    let mut taken: HashSet<String> = slugs.iter().enumerate()
+        .filter(|&(_, slug)| slug.is_empty())
+        .map(|(i, _)| format!("segment-{}", i))
+        .collect();
+    slugs.into_iter().enumerate().map(|(i, slug)| {
+        let anchor = if slug.is_empty() {
+            format!("segment-{}", i)
+        } else if taken.contains(&slug) {
+            (1..).map(|n| format!("{}-{}", slug, n))
+                .find(|a| !taken.contains(a))
+                .expect("ran out of anchors")
+        } else {
+            slug
+        };
+        taken.insert(anchor.clone());
+        anchor
+    }).collect()
+}
+
+/// A heading goes under the previous one if it is deeper.
+fn insert(siblings: &mut Vec<Heading>, heading: Heading) {
+    let deeper = siblings.last().map_or(false, |last| heading.level > last.level);
+    if deeper {
+        insert(&mut siblings.last_mut().expect("no last heading").children, heading);
+    } else {
+        siblings.push(heading);
+    }
+}
+
+/// Titles nested by level, down to `max_depth` (deeper titles are left out).
+pub fn outline(segments: &[Segment], anchors: &[String], max_depth: u8) -> Vec<Heading> {
+    let mut headings = vec![];
+    for (segment, anchor) in segments.iter().zip(anchors.iter()) {
+        if let &Segment::Title((level, ref title)) = segment {
+            if level <= max_depth {
+                insert(&mut headings, Heading { level: level,
+                                                title: title_text(title),
+                                                anchor: anchor.clone(),
+                                                children: vec![] });
+            }
+        }
+    }
+    headings
+}
+
+/// Number of headings in an outline.
+pub fn count(headings: &[Heading]) -> usize {
+    headings.iter().map(|h| 1 + count(&h.children)).sum()
+}
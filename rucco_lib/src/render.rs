@@ -2,78 +2,185 @@ use segment::*;
 use languages::Languages;
 
 use syntect::parsing::{SyntaxSet,SyntaxReference};
-use syntect::highlighting::{ThemeSet, Theme};
-use syntect::html::highlighted_html_for_string;
+use syntect::html::{highlighted_html_for_string, ClassedHTMLGenerator};
+use syntect::util::LinesWithEndings;
 
-use hoedown::{Markdown,Html,Render};
-use hoedown::renderer::html;
+use std::path::Path;
 
-use std::path::{Path};
+use maud::PreEscaped;
 
+use diagram::Diagram;
+use highlight::Highlighting;
+use markdown::Markdown;
+use outline;
 use templates;
-
-thread_local! {
-    static THEME_SET: ThemeSet = ThemeSet::load_defaults();
-    static THEME: Theme = THEME_SET.with(|ts| ts.themes["base16-ocean.dark"].clone());
-    //static SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_nonewlines();
-    static SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-}
+use templates::Page;
 
 /// ----------------------------------------------------------------------------
 /// Rendering a segment
 
-fn render_segment(syntax_ref: &SyntaxReference, segment: Segment) -> RenderedSegment {
-    let mut md_html = Html::new(html::Flags::empty(), 0);
-
+fn render_segment(highlighting: &Highlighting, markdown: &Markdown,
+                  syntax_ref: &SyntaxReference, segment: Segment) -> RenderedSegment {
     match segment {
-        Segment::Title((h, title)) => {
-            let md_doc = Markdown::new(title.as_str());
-            let title_html = md_html.render(&md_doc).to_str().unwrap_or("<p>failed to render title</p>").to_owned();
-            Segment::Title((h, title_html))
-        },
-        Segment::Doc(doc) => {
-            let md_doc = Markdown::new(doc.as_str());
-            let doc_html = md_html.render(&md_doc).to_str().unwrap_or("<p>failed to render doc</p>").to_owned();
-            Segment::Doc(doc_html)
-        },
-        Segment::Code(code) => {
-            THEME.with(move |theme| {
-                let code_html = SYNTAX_SET.with(|ss| {
-                    highlighted_html_for_string(&code, ss, syntax_ref, theme)
-                });
-                Segment::Code(code_html)
-            })
+        Segment::Title((h, title)) => Segment::Title((h, markdown.render(&title))),
+        Segment::Doc(doc) => Segment::Doc(markdown.render_with(&doc, &|language, code| {
+            if let Some(diagram) = language.and_then(Diagram::from_language) {
+                match diagram.to_svg(code) {
+                    Ok(svg) => return Some(html!(div class="diagram" { (PreEscaped(svg)) }).into_string()),
+                    Err(e) => warn!("could not draw {:?} diagram, showing its source: {}", diagram, e)
+                }
+            }
+            doc_code_syntax(&highlighting.syntax_set, syntax_ref, language)
+                .map(|syntax_ref| code_html(highlighting, syntax_ref, code))
+        })),
+        Segment::Code((code, annotations, lines)) => {
+            let code_html = code_html(highlighting, syntax_ref, &code);
+            let code_html = decorate_lines(&code_html, &annotations, &lines, highlighting.line_numbers);
+            Segment::Code((code_html, annotations, lines))
+        }
+    }
+}
+
+fn code_html(highlighting: &Highlighting, syntax_ref: &SyntaxReference, code: &str) -> String {
+    if highlighting.classes {
+        classed_html(code, &highlighting.syntax_set, syntax_ref)
+    } else {
+        highlighted_html_for_string(code, &highlighting.syntax_set, syntax_ref, &highlighting.theme)
+    }
+}
+
+/// The syntax of a code block in the docs: the one its fence names (`rust`,
+/// `rs`, `Rust`...), else the one of the file it documents. Blocks in unknown
+/// languages are left to the markdown renderer.
+fn doc_code_syntax<'s>(ss: &'s SyntaxSet, file_syntax: &'s SyntaxReference,
+                       language: Option<&str>) -> Option<&'s SyntaxReference> {
+    match language {
+        Some(language) => ss.find_syntax_by_token(language),
+        None => Some(file_syntax)
+    }
+}
+
+/// Same layout as `highlighted_html_for_string`, with scope classes styled by
+/// `highlight.css` (its root class is "code").
+fn classed_html(code: &str, ss: &SyntaxSet, syntax_ref: &SyntaxReference) -> String {
+    let mut generator = ClassedHTMLGenerator::new(syntax_ref, ss);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line(line);
+    }
+    ["<pre class=\"code\">\n", &generator.finalize(), "</pre>\n"].concat()
+}
+
+/// Puts a `#L123` anchor (showing the line number in the gutter if asked) at
+/// the start of each line of highlighted code, and its annotations at its end
+/// as margin notes. The opening `<pre>` has a line of its own.
+fn decorate_lines(code_html: &str, annotations: &[Annotation], lines: &[usize], line_numbers: bool) -> String {
+    let mut decorated = String::with_capacity(code_html.len());
+    for (i, line) in code_html.split('\n').enumerate() {
+        if i > 0 {
+            decorated.push('\n');
+            if let Some(number) = lines.get(i - 1) {
+                let id = format!("L{}", number);
+                let anchor = if line_numbers {
+                    html!(a class="line-number" id=(id) href={ "#" (id) } data-line=(number) {})
+                } else {
+                    html!(a class="line-anchor" id=(id) {})
+                };
+                decorated.push_str(&anchor.into_string());
+            }
+        }
+        decorated.push_str(line);
+        for &(_, ref note) in annotations.iter().filter(|&&(l, _)| l + 1 == i) {
+            decorated.push_str(&html!(span class="code-note" title=(note) { (note) }).into_string());
         }
     }
+    decorated
 }
 
 /// ----------------------------------------------------------------------------
 /// Rendering a source file
 
-pub fn render
+/// Extracts the raw segments of a source file: doc is still markdown and code
+/// is not highlighted.
+pub fn raw_segments
     (languages: &mut Languages,
+     extension: &str,
+     source_text: &str) -> Option<Vec<Segment>>
+{
+    if let &Some(ref lang) = languages.get(extension) {
+        Some(extract_segments(lang, source_text).collect())
+    } else {
+        debug!("could not build section parser for extension: {}", extension);
+        None
+    }
+}
+
+/// The sublime syntax of a language: its `syntax`, or else its `highlight`
+/// extension, or else its own extension.
+fn find_syntax<'s>(ss: &'s SyntaxSet, languages: &mut Languages, extension: &str) -> Option<&'s SyntaxReference> {
+    let highlight = languages.highlight_extension(extension);
+    languages.highlight_syntax(extension).as_ref()
+        .and_then(|name| ss.find_syntax_by_name(name))
+        .or_else(|| ss.find_syntax_by_extension(&highlight))
+}
+
+/// Whether the code of a language gets syntax highlighting, or is rendered
+/// as plain text.
+pub fn is_highlighted(highlighting: &Highlighting, languages: &mut Languages, extension: &str) -> bool {
+    find_syntax(&highlighting.syntax_set, languages, extension).is_some()
+}
+
+/// Extracts the segments of a source file and renders them (markdown and
+/// syntax highlighting), leaving the templating for later: the template needs
+/// to know about every other rendered file to build its navigation.
+///
+/// Code of languages sublime has no syntax for is rendered as plain text.
+/// Code blocks in docs are highlighted too, as the file's code if untagged,
+/// and diagrams (`dot`, `sequence`) are drawn.
+pub fn render_segments
+    (highlighting: &Highlighting,
+     markdown: &Markdown,
+     languages: &mut Languages,
+     extension: &str,
+     source_text: &str) -> Option<Vec<RenderedSegment>>
+{
+    raw_segments(languages, extension, source_text)
+        .map(|segments| render_raw_segments(highlighting, markdown, languages, extension, segments))
+}
+
+/// Renders the raw segments of a source file, see `render_segments`.
+pub fn render_raw_segments
+    (highlighting: &Highlighting,
+     markdown: &Markdown,
+     languages: &mut Languages,
+     extension: &str,
+     segments: Vec<Segment>) -> Vec<RenderedSegment>
+{
+    let ss = &highlighting.syntax_set;
+    let syntax_ref = find_syntax(ss, languages, extension).unwrap_or_else(|| {
+        debug!("no sublime syntax available for extension: {}, rendering plain text", extension);
+        ss.find_syntax_plain_text()
+    });
+    segments.into_iter().map(|s| render_segment(highlighting, markdown, syntax_ref, s)).collect()
+}
+
+/// Renders a lone source file, with no other pages to link to.
+pub fn render
+    (highlighting: &Highlighting,
+     markdown: &Markdown,
+     languages: &mut Languages,
      extension: &str,
      source_text: &str,
      source_path: &Path,
-     css_rel_path: &str) -> Option<String>
+     root_rel_path: &str) -> Option<String>
 {
-    SYNTAX_SET.with(|ss| {
-        if let Some(syntax_ref) = ss.find_syntax_by_extension(extension) {
-            if let &Some(ref lang) = languages.get(extension) {
-                let sections: Vec<Segment> =
-                    extract_segments(lang, source_text)
-                    .map(|s| render_segment(syntax_ref,s)).collect();
-                Some(templates::classic::render(vec![].iter(),
-                                                css_rel_path,
-                                                source_path,
-                                                sections.iter()))
-            } else {
-                debug!("could not build section parser for extension: {}", extension);
-                None
-            }
-        } else {
-            debug!("no sublime syntax available for extension: {}", extension);
-            None
-        }
+    let language = languages.highlight_extension(extension);
+    render_segments(highlighting, markdown, languages, extension, source_text).map(|segments| {
+        templates::classic::render(&Page { root: root_rel_path,
+                                           docfiles: &[],
+                                           source_path: source_path,
+                                           language: &language,
+                                           segments: &segments,
+                                           toc_depth: outline::DEFAULT_DEPTH,
+                                           highlight_css: highlighting.classes })
     })
 }
@@ -0,0 +1,74 @@
+//! Client-side search: the rendered titles and docs of every page are gathered
+//! into a `search-index.json` index, for other tools, and the same index in a
+//! `search-index.js` script that the search box (see `templates::search`)
+//! loads on first use: browsers do not let pages opened from `file://`
+//! request json.
+//!
+//! The index is kept compact: page paths are stored once, and each entry is
+//! an array `[page, anchor, title, text]` where `page` indexes `pages`.
+
+use outline::{anchors, plain_text, title_text};
+use segment::RenderedSegment;
+use serde_json;
+use std::path::Path;
+
+pub const INDEX_FILE: &'static str = "search-index.json";
+pub const INDEX_SCRIPT_FILE: &'static str = "search-index.js";
+/// set by the index script
+pub const INDEX_VARIABLE: &'static str = "rucco_search_index";
+
+#[derive(Serialize, Default)]
+pub struct SearchIndex {
+    /// pages, relative to the output directory
+    pages: Vec<String>,
+    entries: Vec<(usize, String, String, String)>
+}
+
+impl SearchIndex {
+    pub fn new() -> SearchIndex {
+        SearchIndex::default()
+    }
+
+    /// A title starts a new entry, following docs are added to it. Docs coming
+    /// before any title get an entry of their own, titled after the page.
+    pub fn add_page(&mut self, docfile: &Path, segments: &[RenderedSegment]) {
+        let page = self.pages.len();
+        let page_path = docfile.to_str().expect("failed to convert doc file path to string");
+        self.pages.push(page_path.to_owned());
+
+        let segment_anchors = anchors(segments);
+        let mut current: Option<(usize, String, String, String)> = None;
+        for (segment, anchor) in segments.iter().zip(segment_anchors.into_iter()) {
+            match segment {
+                &RenderedSegment::Title((_, ref html)) => {
+                    if let Some(entry) = current.take() {
+                        self.entries.push(entry);
+                    }
+                    current = Some((page, anchor, title_text(html), String::new()));
+                },
+                &RenderedSegment::Doc(ref html) => {
+                    let entry = current.get_or_insert_with(|| {
+                        (page, anchor, page_path.to_owned(), String::new())
+                    });
+                    if !entry.3.is_empty() {
+                        entry.3.push(' ');
+                    }
+                    entry.3.push_str(&plain_text(html));
+                },
+                &RenderedSegment::Code(_) => {}
+            }
+        }
+        if let Some(entry) = current {
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize search index")
+    }
+
+    /// The content of `INDEX_SCRIPT_FILE`.
+    pub fn to_js(&self) -> String {
+        format!("var {} = {};\n", INDEX_VARIABLE, self.to_json())
+    }
+}
@@ -1,55 +1,59 @@
-use regex;
 use std;
+use std::collections::VecDeque;
 use regex::{Regex,RegexBuilder};
+use languages::Syntax;
+use lexer::{tokenize, Lexer, Token};
+use literate::literate_segments;
+
+/// A trailing comment of some code: (line within the code segment, comment)
+/// `int x; // moo` -> (0, "moo")
+pub type Annotation = (usize, String);
+
+/// Source line number (from 1) of each line of a code segment.
+pub type LineNumbers = Vec<usize>;
 
 #[derive(Debug,Clone)]
 pub enum Segment {
     Title((u8, String)), // "## lol" -> (2, "## lol")
-    Code(String),
+    Code((String, Vec<Annotation>, LineNumbers)), // annotations only with `[input] annotations`
     Doc(String)
 }
 
 pub use segment::Segment as RenderedSegment;
 
-pub fn extract_segments<'r, 't>(r: &'r regex::Regex, source: &'t str)
-                                -> //impl Iterator<Item=Segment>+'r+'t
-DenseSegments<'r, 't>
-{
-    let sparse_segments: SparseSegments<'r, 't> =
-        SparseSegments {
-            code_and_doc_captures: r.captures_iter(source),
-            title_and_doc_in_multiline_capture: None
-        };
+/// Iterator<Item=Segment>
+pub type Segments<'a> = DenseSegments<Box<dyn Iterator<Item=Option<Segment>> + 'a>>;
 
-    let dense_segments: DenseSegments<'r, 't> =
-        DenseSegments { segments: sparse_segments, cur: None };
+pub fn extract_segments<'a>(syntax: &'a Syntax, source: &'a str) -> Segments<'a> {
+    let sparse_segments: Box<dyn Iterator<Item=Option<Segment>> + 'a> = match syntax.literate {
+        Some(literate) => Box::new(literate_segments(literate, &syntax.fence_tags, source).into_iter().map(Some)),
+        None => Box::new(SparseSegments {
+            syntax: syntax,
+            tokens: tokenize(syntax, source),
+            line_number: 1,
+            pending: VecDeque::new()
+        })
+    };
 
-    dense_segments
+    DenseSegments { segments: sparse_segments, cur: None }
 }
 
 // -----------------------------------------------------------------------------
 // ## Extracting segments
 
-/// Iterator<Item=Option<Segment>>
-struct SparseSegments<'r, 't> {
-    /// our regex captures that split doc from code
-    code_and_doc_captures: regex::CaptureMatches<'r, 't>,
-    /// necessary for splitting multilines into titles and doc lines
-    title_and_doc_in_multiline_capture: Option<regex::CaptureMatches<'r, 't>>
+/// Iterator<Item=Option<Segment>>, going through the source line by line
+/// (a line ends with a newline token, so block comments and multiline strings
+/// make longer "lines").
+struct SparseSegments<'s, 't> {
+    syntax: &'s Syntax,
+    tokens: Lexer<'s, 't>,
+    /// source line the next line of tokens starts at
+    line_number: usize,
+    /// a line can give several segments (block comments)
+    pending: VecDeque<Segment>
 }
 
 lazy_static! {
-    /// regex to extract title and doc from multiline comments
-    static ref ML_MARGIN_L_RE: Regex = // multiline with margin
-        RegexBuilder::new(r"^[ \t\n]*[^ \t\n]+ ?([^\n]*\n?)$")
-        .multi_line(true)
-        .dot_matches_new_line(true)
-        .build().expect("Wrong multiline split regexp");
-    static ref ML_NOMARGIN_L_RE: Regex = // multiline with no margin
-        RegexBuilder::new(r"^[\n\t]*([^\n]*\n?)$")
-        .multi_line(true)
-        .dot_matches_new_line(true)
-        .build().expect("Wrong multiline split regexp");
     static ref TITLE_SPLIT_RE: Regex = // split title between '##' and 'actual title'
         RegexBuilder::new(r"^(#+).*")
         .multi_line(true)
@@ -57,7 +61,7 @@ lazy_static! {
         .build().expect("Wrong title split regexp!");
 }
 
-fn title_or_doc_segment(line: &str) -> Segment {
+pub fn title_or_doc_segment(line: &str) -> Segment {
     if let Some(heading_capture) = TITLE_SPLIT_RE.captures_iter(line).next() {
         if let Some(h) = heading_capture.get(1) {
             Segment::Title(( (h.end() - h.start()) as u8, line.to_owned()))
@@ -70,97 +74,215 @@ fn title_or_doc_segment(line: &str) -> Segment {
     }
 }
 
-impl<'r, 't> Iterator for SparseSegments<'r, 't> {
-    type Item=Option<Segment>;
+/// "// moo" -> "moo\n" (an empty mark, as in markdown, keeps the whole line)
+fn single_doc_line(text: &str, body: &str) -> String {
+    let line = if text.len() > body.len() && body.starts_with(' ') {
+        &body[1..]
+    } else {
+        body
+    };
+    [line.trim_end_matches('\r'), "\n"].concat()
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
+/// Lines of a block comment (or docstring) body: the text right after the
+/// header, then the following lines stripped of their margin (or else of their
+/// common indentation). Blank first and last lines are dropped.
+fn block_doc_lines(margin: Option<&Regex>, body: &str) -> Vec<String> {
+    let mut lines: Vec<&str> = body.split('\n').collect();
+    let first = lines.remove(0);
+    if lines.last().map_or(false, |l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    let margin = margin.filter(|margin| {
+        lines.iter().all(|l| l.trim().is_empty() || margin.is_match(l.trim_start()))
+    });
+    let indent = lines.iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min().unwrap_or(0);
 
-        let mut drop_current_multiline_capture = false;
-
-        let segment =
-            if let Some(ref mut bm) = self.title_and_doc_in_multiline_capture {
-                // in multiline doc context
-                if let Some(c) = bm.next() {
-                    if let Some(l) = c.get(1) {
-                        Some(Some(title_or_doc_segment(l.as_str())))
-                    } else {
-                        Some(None)
-                    }
-                } else {
-                    drop_current_multiline_capture = true; // hope we can drop that when MIR is online
-                    Some(None)
+    let mut doc_lines = vec![];
+    let first = first.trim();
+    if !first.is_empty() {
+        doc_lines.push([first, "\n"].concat());
+    }
+    for line in lines {
+        let line = match margin {
+            Some(margin) => {
+                let line = line.trim_start();
+                let unmarked = margin.find(line).map_or(line, |m| &line[m.end()..]);
+                if unmarked.starts_with(' ') { &unmarked[1..] } else { unmarked }
+            },
+            None => line.get(indent..).unwrap_or("")
+        };
+        doc_lines.push([line.trim_end_matches('\r'), "\n"].concat());
+    }
+    doc_lines
+}
+
+impl<'s, 't> SparseSegments<'s, 't> {
+    /// Every comment is doc, but in doc-only mode.
+    fn is_doc(&self, comment: &str, doc_mark: &Option<Regex>) -> bool {
+        !self.syntax.doc_only || doc_mark.as_ref().map_or(false, |mark| mark.is_match(comment))
+    }
+
+    /// A comment alone on its line is doc, anything else is code. A block
+    /// comment can be followed by code on its last line.
+    fn segment_line(&mut self, line: &[Token<'t>], newline: bool) {
+        let first = line.iter().position(|t| !t.is_blank());
+        let code_from = match first.map(|i| (i, &line[i])) {
+            Some((_, &Token::LineComment { text, body }))
+                if self.is_doc(text, &self.syntax.doc_singleline) => {
+                self.pending.push_back(title_or_doc_segment(&single_doc_line(text, body)));
+                None
+            },
+            Some((i, &Token::BlockComment { text, body }))
+                if self.is_doc(text, &self.syntax.doc_multiline_header) => {
+                for doc_line in block_doc_lines(self.syntax.multiline_margin.as_ref(), body) {
+                    self.pending.push_back(title_or_doc_segment(&doc_line));
                 }
-            } else {
-                // primary context
-                let capture = self.code_and_doc_captures.next();
-                if let Some(c) = capture {
-                    match (c.name("doc_sl"),c.name("doc_ml"),c.name("doc_ml_h"),c.name("doc_ml_l"),c.name("code")) {
-                        (None,None,None,None,None) => Some(None), // ignore
-                        (Some(sl),_,_,_,_) => // single comment line
-                            Some(Some(title_or_doc_segment(sl.as_str()))),
-                        (_,Some(ml),_,_,_) => { // multiline no margin
-                            self.title_and_doc_in_multiline_capture = Some(ML_NOMARGIN_L_RE.captures_iter(ml.as_str()));
-                            Some(None)
-                        },
-                        (_,_,Some(ml_h),Some(ml_l),_) => { // multiline with margin
-                            self.title_and_doc_in_multiline_capture = Some(ML_MARGIN_L_RE.captures_iter(ml_l.as_str()));
-                            Some(Some(title_or_doc_segment(ml_h.as_str())))
-                        },
-                        (_,_,_,_,Some(code)) =>  // code
-                            Some(Some(Segment::Code(code.as_str().to_owned()))),
-                        (_,_,_,_,_) => {
-                            error!("Something went wrong when processing SparseSegments");
-                            None
-                        }
-                    }
-                } else {
-                    None
+                Some(i + 1).filter(|&rest| line[rest..].iter().any(|t| !t.is_blank()))
+            },
+            // always doc
+            Some((_, &Token::Docstring { body, .. })) => {
+                for doc_line in block_doc_lines(None, body) {
+                    self.pending.push_back(title_or_doc_segment(&doc_line));
                 }
+                None
+            },
+            // blank lines and plain comments (in doc-only mode) are code too
+            _ => Some(0)
+        };
+        if let Some(code_from) = code_from {
+            let (tokens, note) = match self.trailing_comment(&line[code_from..]) {
+                Some((tokens, note)) => (tokens, Some(note)),
+                None => (&line[code_from..], None)
+            };
+            let mut code: String = tokens.iter().map(Token::text).collect();
+            if code_from > 0 {
+                code = code.trim_start().to_owned();
+            }
+            let annotations = match note {
+                Some(note) => {
+                    code = code.trim_end().to_owned();
+                    vec![(code.matches('\n').count(), note.to_owned())]
+                },
+                None => vec![]
             };
+            if newline {
+                code.push('\n');
+            }
+            let first_line = self.line_number + line[..code_from].iter()
+                .map(|t| t.text().matches('\n').count())
+                .sum::<usize>();
+            let lines = (first_line..first_line + code.lines().count()).collect();
+            self.pending.push_back(Segment::Code((code, annotations, lines)));
+        }
+    }
+
+    /// `int x; // moo` -> the code before the comment, and "moo" (when
+    /// annotations are on).
+    fn trailing_comment<'l>(&self, line: &'l [Token<'t>]) -> Option<(&'l [Token<'t>], &'t str)> {
+        if !self.syntax.annotations {
+            return None;
+        }
+        let last = line.iter().rposition(|t| !t.is_blank())?;
+        match line[last] {
+            Token::LineComment { body, .. }
+                if !body.trim().is_empty() && line[..last].iter().any(|t| !t.is_blank()) =>
+                Some((&line[..last], body.trim())),
+            _ => None
+        }
+    }
+}
+
+impl<'s, 't> Iterator for SparseSegments<'s, 't> {
+    type Item=Option<Segment>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(segment) = self.pending.pop_front() {
+            return Some(Some(segment));
+        }
 
-        if drop_current_multiline_capture {
-            self.title_and_doc_in_multiline_capture = None;
+        let mut line = vec![];
+        let mut newline = false;
+        while let Some(token) = self.tokens.next() {
+            if token == Token::Newline {
+                newline = true;
+                break;
+            }
+            line.push(token);
+        }
+        if line.is_empty() && !newline {
+            return None; // we're done
         }
 
-        segment
+        self.segment_line(&line, newline);
+        self.line_number += line.iter().map(|t| t.text().matches('\n').count()).sum::<usize>() + 1;
+        Some(self.pending.pop_front())
     }
 }
 
 // -----------------------------------------------------------------------------
 // ## Compacting segments
 
+fn is_blank(text: &str) -> bool {
+    text.trim().is_empty()
+}
+
+/// Trailing blank lines only separate code from the next doc.
+fn trim_code(segment: Option<Segment>) -> Option<Segment> {
+    match segment {
+        Some(Segment::Code((code, annotations, mut lines))) => {
+            let code = [code.trim_end(), "\n"].concat();
+            lines.truncate(code.lines().count());
+            Some(Segment::Code((code, annotations, lines)))
+        },
+        segment => segment
+    }
+}
+
 /// Iterator<Item=<Segment>>
-pub struct DenseSegments<'r, 't> {
-    segments: SparseSegments<'r, 't>,
+pub struct DenseSegments<I> {
+    segments: I,
     cur: Option<Segment>
 }
 
-impl<'r, 't> Iterator for DenseSegments<'r, 't> {
+impl<I: Iterator<Item=Option<Segment>>> Iterator for DenseSegments<I> {
     type Item=Segment;
 
     fn next(&mut self) -> Option<Segment> {
         loop {
             match (&mut self.cur, self.segments.next()) {
                 // we're done
-                (cur, None) => return std::mem::replace(cur, None),
+                (cur, None) => return trim_code(std::mem::replace(cur, None)),
                 // skip dud
                 (_, Some(None)) => continue,
+                // blank lines within code or doc are kept, others dropped
+                (&mut Some(Segment::Code((ref mut c, ref mut a, ref mut l))),
+                 Some(Some(Segment::Code((ref n, ref an, ref ln))))) => {
+                    let offset = c.matches('\n').count();
+                    a.extend(an.iter().map(|&(line, ref note)| (line + offset, note.clone())));
+                    c.push_str(n.as_str());
+                    l.extend(ln);
+                },
+                (&mut Some(Segment::Doc(ref mut c)), Some(Some(Segment::Code((ref n, _, _))))) if is_blank(n) => {
+                    c.push('\n');
+                },
+                (_, Some(Some(Segment::Code((ref n, _, _))))) if is_blank(n) => continue,
                 // first one! (no self.cur)
                 (cur @ &mut None, Some(n)) => {
                     std::mem::replace(cur, n);
                 },
                 // ---- ok we're left with Some(_),Some(Some(_))
                 // same (=> append, except title, cannot append titles! they switch!)
-                (&mut Some(Segment::Code(ref mut c)),Some(Some(Segment::Code(ref n)))) => {
-                    c.push_str(n.as_str());
-                },
                 (&mut Some(Segment::Doc(ref mut c)),Some(Some(Segment::Doc(ref n)))) => {
                     c.push_str(n.as_str());
                 },
                 // different (=> switch)
                 (cur, Some(n)) => {
                     let res = std::mem::replace(cur, n);
-                    return res;
+                    return trim_code(res);
                 },
             }
         }
@@ -0,0 +1,88 @@
+//! Symbols defined in the documented sources, for intra-doc links: a doc
+//! comment's [`Languages::get`] links to the line defining `get` in the `impl
+//! Languages` block, whichever file it is in (see `links::Links`).
+//!
+//! Definitions are found by the `definitions` regexes of the languages (their
+//! `name` group is the defined identifier) at the start of code lines. They
+//! qualify the definitions indented below them, and so do `scopes`, which
+//! define nothing themselves:
+//!
+//! ```toml
+//! [languages.rs]
+//! definitions = ['(?:pub\s+)?fn\s+(?P<name>\w+)', '(?:pub\s+)?struct\s+(?P<name>\w+)']
+//! scopes = ['impl(?:<[^>]*>)?\s+(?:[\w:]+\s+for\s+)?(?P<name>\w+)']
+//! ```
+
+use languages::{Languages, Syntax};
+use segment::Segment;
+
+use regex::Regex;
+
+/// A definition: its qualified name (`["Languages", "get"]`) and its line.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Definition {
+    pub path: Vec<String>,
+    pub line: usize
+}
+
+fn captured_name(regexes: &[Regex], text: &str) -> Option<String> {
+    regexes.iter()
+        .filter_map(|r| r.captures(text))
+        .filter_map(|c| c.name("name").map(|m| m.as_str().to_owned()))
+        .next()
+}
+
+/// The definitions in the code of a file, in order.
+pub fn definitions(syntax: &Syntax, segments: &[Segment]) -> Vec<Definition> {
+    let mut found = vec![];
+    // indentation and name of the enclosing definitions and scopes
+    let mut enclosing: Vec<(usize, String)> = vec![];
+    for segment in segments {
+        if let &Segment::Code((ref code, _, ref lines)) = segment {
+            for (text, &line) in code.lines().zip(lines.iter()) {
+                let trimmed = text.trim_start();
+                if trimmed.is_empty() {
+                    continue;
+                }
+                let indentation = text.len() - trimmed.len();
+                while enclosing.last().map_or(false, |&(i, _)| i >= indentation) {
+                    enclosing.pop();
+                }
+                if let Some(name) = captured_name(&syntax.definitions, trimmed) {
+                    let mut path: Vec<String> = enclosing.iter().map(|&(_, ref n)| n.clone()).collect();
+                    path.push(name.clone());
+                    found.push(Definition { path: path, line: line });
+                    enclosing.push((indentation, name));
+                } else if let Some(name) = captured_name(&syntax.scopes, trimmed) {
+                    enclosing.push((indentation, name));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// The definitions in the raw segments of a source file, none if its
+/// language has no `definitions`.
+pub fn segment_definitions(languages: &mut Languages, extension: &str, segments: &[Segment]) -> Vec<Definition> {
+    match languages.get(extension) {
+        &Some(ref syntax) => definitions(syntax, segments),
+        &None => vec![]
+    }
+}
+
+/// `crate::languages::Languages::get()` -> `["languages", "Languages", "get"]`,
+/// `os.path` -> `["os", "path"]`.
+pub fn link_path(link: &str) -> Vec<&str> {
+    let link = link.trim_end_matches("()").trim_end_matches('!');
+    link.split(|c: char| c == ':' || c == '.')
+        .filter(|s| !s.is_empty())
+        .skip_while(|&s| s == "crate" || s == "self" || s == "super")
+        .collect()
+}
+
+/// Whether a qualified name ends with a link path.
+pub fn matches(path: &[String], link: &[&str]) -> bool {
+    !link.is_empty() && path.len() >= link.len()
+        && path[path.len() - link.len()..].iter().zip(link.iter()).all(|(p, l)| p == l)
+}
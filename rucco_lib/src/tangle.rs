@@ -0,0 +1,76 @@
+//! Tangling: the code of a literate file, put back together as a source file.
+//!
+//! Code blocks are written out in order, unless they are named chunks
+//! (noweb style): a block whose first line is `<<name>>=` defines (or extends)
+//! the chunk `name`, and a line holding only `<<name>>` gets replaced by it,
+//! indentation included. When a `<<*>>=` chunk is defined, it is the output
+//! and unnamed blocks are left out. So are blocks tagged with another language
+//! (```` ```sh ```` examples).
+
+use literate::{code_blocks, Literate};
+use regex::Regex;
+use std::collections::HashMap;
+
+lazy_static! {
+    static ref DEFINITION_RE: Regex =
+        Regex::new(r"^\s*<<(.+?)>>=\s*$").expect("Wrong chunk definition regexp");
+    static ref REFERENCE_RE: Regex =
+        Regex::new(r"^(\s*)<<(.+?)>>\s*$").expect("Wrong chunk reference regexp");
+}
+
+const ROOT_CHUNK: &'static str = "*";
+
+type Chunks<'t> = HashMap<&'t str, Vec<&'t str>>;
+
+fn expand<'t>(chunks: &Chunks<'t>, lines: &[&'t str], indent: &str, stack: &mut Vec<&'t str>,
+              out: &mut String, problems: &mut Vec<String>) {
+    for line in lines {
+        if let Some(c) = REFERENCE_RE.captures(line) {
+            let name = c.get(2).expect("no chunk name").as_str();
+            let chunk_indent = [indent, c.get(1).map_or("", |m| m.as_str())].concat();
+            if stack.contains(&name) {
+                problems.push(format!("recursive chunk: <<{}>>", name));
+            } else if let Some(chunk) = chunks.get(name) {
+                stack.push(name);
+                expand(chunks, chunk, &chunk_indent, stack, out, problems);
+                stack.pop();
+            } else {
+                problems.push(format!("undefined chunk: <<{}>>", name));
+            }
+            continue;
+        }
+        if !line.is_empty() {
+            out.push_str(indent);
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+/// The source code of a literate file, whose code goes by fence `tags`
+/// (`Syntax::fence_tags`). Also returns the undefined or recursive chunk
+/// references.
+pub fn tangle(literate: Literate, tags: &[String], source: &str) -> (String, Vec<String>) {
+    let mut chunks: Chunks = HashMap::new();
+    let mut unnamed: Vec<&str> = vec![];
+    for block in code_blocks(literate, tags, source) {
+        let name = DEFINITION_RE.captures(block[0])
+            .and_then(|c| c.get(1))
+            .map(|m| m.as_str());
+        match name {
+            Some(name) => chunks.entry(name).or_insert_with(Vec::new).extend(&block[1..]),
+            None => {
+                if !unnamed.is_empty() {
+                    unnamed.push("");
+                }
+                unnamed.extend(block);
+            }
+        }
+    }
+
+    let mut out = String::with_capacity(source.len());
+    let mut problems = vec![];
+    let root = chunks.get(ROOT_CHUNK).cloned().unwrap_or(unnamed);
+    expand(&chunks, &root, "", &mut vec![ROOT_CHUNK], &mut out, &mut problems);
+    (out, problems)
+}
@@ -1,6 +1,8 @@
 use segment::RenderedSegment;
-use std::path::{Path,PathBuf};
+use std::path::PathBuf;
 use maud::PreEscaped;
+use outline::count;
+use templates::{index, search, toc, Page};
 
 // #[cfg(test)]
 // mod tests {
@@ -22,15 +24,12 @@ use maud::PreEscaped;
 //     }
 // }
 
-pub fn render<'a,
-              T: Iterator<Item=&'a RenderedSegment> + Clone + Sized,
-              U: Iterator<Item=&'a PathBuf> + Clone + Sized>
-    (docfiles: U,
-     css_path: &'a str,
-     source_path: &'a Path,
-     segments: T)
-     -> String
+pub fn render(page: &Page) -> String
 {
+    let (docfiles, segments) = (page.docfiles.iter(), page.segments.iter());
+    let source_path = page.source_path;
+    let css_path = page.css_path();
+    let css_path = css_path.as_str();
     let mut peek_segments = segments.clone().peekable();
     let (has_global_title, title_to_use): (bool, String) =
         if let Some(&&RenderedSegment::Title((_, ref t))) = peek_segments.peek() {
@@ -43,6 +42,8 @@ pub fn render<'a,
         };
 
     let docfiles_count = docfiles.clone().count();
+    let anchors = page.anchors();
+    let outline = page.outline(&anchors);
 
     html! [
         head {
@@ -50,10 +51,14 @@ pub fn render<'a,
             meta http-equiv="content-type" content="text/html; charset=UTF-8" {}
             meta name="viewport" content="width=device-width, target-densitydpi=160dpi, initial-scale=1.0, maximum-scale=1.0, user-scalable=0" {}
             link rel="stylesheet" media="all" href=(css_path) {}
+            @if let Some(highlight_css_path) = page.highlight_css_path() {
+                link rel="stylesheet" media="all" href=(highlight_css_path) {}
+            }
         }
         body {
             div#container {
                 div#background {}
+                (search::search_box(page.root))
                 @if docfiles_count > 1 {
                     ul#jump_to {
                         li {
@@ -63,7 +68,7 @@ pub fn render<'a,
                                 div#jump_page {
                                     @for docfile in docfiles {
                                         a.source href=(docfile.to_str().unwrap()) {
-                                            (docfile.file_name().unwrap().to_str().unwrap())
+                                            (index::docfile_label(docfile))
                                         }
                                     }
                                 }
@@ -79,13 +84,20 @@ pub fn render<'a,
                             }
                         }
                     }
-                    @for (i, segment) in segments.enumerate() {
-                        li id={ "segment-" (i) } {
+                    @if count(&outline) > 1 {
+                        li#toc {
+                            div.annotation {
+                                (toc::toc(&outline))
+                            }
+                        }
+                    }
+                    @for (segment, anchor) in segments.zip(anchors.iter()) {
+                        li id=(anchor) {
                             @match segment {
                                 &RenderedSegment::Title((ref level,ref html)) => {
                                     div.annotation {
                                         div class={ "pilwrap for-" (level) } {
-                                            a.pilcrow href={ "#segment-" (i) } { "¶" }
+                                            a.pilcrow href={ "#" (anchor) } { "¶" }
                                         }
                                         (PreEscaped(&html))
                                     }
@@ -94,12 +106,12 @@ pub fn render<'a,
                                 &RenderedSegment::Doc(ref doc) => {
                                     div.annotation {
                                         div class={ "pilwrap" } {
-                                            a.pilcrow href={ "#segment-" (i) } { "¶" }
+                                            a.pilcrow href={ "#" (anchor) } { "¶" }
                                         }
                                     }
                                     (PreEscaped(&doc))
                                 },
-                                &RenderedSegment::Code(ref code) => {
+                                &RenderedSegment::Code((ref code, _, _)) => {
                                     div.content {
                                         (PreEscaped(&code))
                                     }
@@ -112,3 +124,30 @@ pub fn render<'a,
         }
     ].into_string()
 }
+
+/// The index page, listing every documented file.
+pub fn render_index<'a>(css_path: &'a str, docfiles: &'a [PathBuf]) -> String
+{
+    html! [
+        head {
+            title { "Index" }
+            meta http-equiv="content-type" content="text/html; charset=UTF-8" {}
+            meta name="viewport" content="width=device-width, target-densitydpi=160dpi, initial-scale=1.0, maximum-scale=1.0, user-scalable=0" {}
+            link rel="stylesheet" media="all" href=(css_path) {}
+        }
+        body {
+            div#container {
+                div#background {}
+                (search::search_box(""))
+                ul.sections {
+                    li#title {
+                        div.annotation {
+                            h1 { "Index" }
+                            (index::tree(docfiles))
+                        }
+                    }
+                }
+            }
+        }
+    ].into_string()
+}
@@ -0,0 +1,142 @@
+//! User-provided templates: a directory holding a `page.html` tera template,
+//! optionally an `index.html` one, and static assets (css, images, fonts...)
+//! that get copied as-is in the output directory. Every other `.html` file is
+//! a template too, for `page.html` and `index.html` to extend, include or
+//! import macros from (`{% extends "base.html" %}`).
+//!
+//! Pages get the following variables (segments html must go through `| safe`):
+//! - `root`: path from the page back to the output directory ("../../")
+//! - `css_path`: `root` followed by "style.css"
+//! - `highlight_css_path`: `root` followed by "highlight.css", when the code is
+//!   highlighted with classes (empty otherwise)
+//! - `source_path`: path of the documented file
+//! - `docfiles`: the generated pages, as `{ href, label }` (href is relative to the page)
+//! - `segments`: as `{ kind, level, html, annotations, lines, anchor }`, kind
+//!   being "title", "doc" or "code" (annotations are `[line, note]` pairs and
+//!   lines the source line numbers, for code)
+//! - `toc`: the html of the page table of contents (empty under two titles)
+//! - `search_box`: the html of the search box
+//!
+//! The index gets `root`, `css_path`, `docfiles`, `search_box` and `tree` (the
+//! files as nested html lists).
+
+use segment::{Annotation, Segment};
+use std::path::{Path,PathBuf};
+use tera::{Tera, Context};
+use templates::{index, search, toc, Layout, Page, Template};
+
+const PAGE_TEMPLATE: &'static str = "page.html";
+const INDEX_TEMPLATE: &'static str = "index.html";
+
+#[derive(Serialize)]
+struct DocFile {
+    href: String,
+    label: String
+}
+
+#[derive(Serialize)]
+struct SegmentContext<'a> {
+    kind: &'static str,
+    /// heading level for titles, 0 otherwise
+    level: u8,
+    html: &'a str,
+    /// trailing comments of code, already in `html` as `span.code-note`
+    annotations: &'a [Annotation],
+    /// source line numbers of code, already in `html` as `#L123` anchors
+    lines: &'a [usize],
+    anchor: String
+}
+
+fn docfiles_context(docfiles: &[PathBuf]) -> Vec<DocFile> {
+    docfiles.iter().map(|d| DocFile {
+        href: d.to_str().expect("failed to convert doc file path to string").to_owned(),
+        label: index::docfile_label(d)
+    }).collect()
+}
+
+fn segments_context<'a>(segments: &'a [Segment], anchors: Vec<String>) -> Vec<SegmentContext<'a>> {
+    segments.iter().zip(anchors.into_iter()).map(|(segment, anchor)| {
+        let (kind, level, html, annotations, lines) = match segment {
+            &Segment::Title((level, ref html)) => ("title", level, html, &[][..], &[][..]),
+            &Segment::Doc(ref html) => ("doc", 0, html, &[][..], &[][..]),
+            &Segment::Code((ref html, ref annotations, ref lines)) =>
+                ("code", 0, html, annotations.as_slice(), lines.as_slice())
+        };
+        SegmentContext { kind: kind, level: level, html: html.as_str(),
+                         annotations: annotations, lines: lines, anchor: anchor }
+    }).collect()
+}
+
+pub struct TemplateDir {
+    tera: Tera,
+    has_index: bool
+}
+
+impl TemplateDir {
+    /// Loads every `.html` file of `dir`, named by its path relative to it.
+    pub fn load(dir: &Path) -> Result<TemplateDir, ::tera::Error> {
+        let glob = dir.join("**").join("*.html");
+        let tera = Tera::new(glob.to_str().expect("failed to convert template dir path to string"))?;
+        let has_template = |name: &str| tera.get_template_names().any(|n| n == name);
+        if !has_template(PAGE_TEMPLATE) {
+            return Err(::tera::Error::msg(format!("no {} in template dir {}", PAGE_TEMPLATE, dir.display())));
+        }
+        let has_index = has_template(INDEX_TEMPLATE);
+        Ok(TemplateDir { tera: tera, has_index: has_index })
+    }
+
+    /// Everything in a template directory but the templates is a static asset.
+    /// `relative` is relative to the template directory.
+    pub fn is_template(relative: &Path) -> bool {
+        relative.extension().map_or(false, |e| e == "html")
+    }
+
+    fn render(&self, name: &str, context: &Context) -> Option<String> {
+        match self.tera.render(name, context) {
+            Ok(rendered) => Some(rendered),
+            Err(e) => {
+                error!("failed to render template {}: {}", name, e);
+                None
+            }
+        }
+    }
+}
+
+impl Template for TemplateDir {
+    fn is_html(&self) -> bool {
+        true
+    }
+
+    fn extension(&self) -> &str {
+        "html"
+    }
+
+    fn render_page(&self, page: &Page) -> Option<String> {
+        let mut context = Context::new();
+        context.insert("root", page.root);
+        context.insert("css_path", &page.css_path());
+        context.insert("highlight_css_path", &page.highlight_css_path().unwrap_or_default());
+        context.insert("source_path", &page.source_path.to_str()
+                       .expect("failed to convert file path to string"));
+        context.insert("docfiles", &docfiles_context(page.docfiles));
+        let anchors = page.anchors();
+        context.insert("toc", &toc::toc(&page.outline(&anchors)).into_string());
+        context.insert("segments", &segments_context(page.segments, anchors));
+        context.insert("search_box", &search::search_box(page.root).into_string());
+        self.render(PAGE_TEMPLATE, &context)
+    }
+
+    fn render_index(&self, docfiles: &[PathBuf]) -> Option<String> {
+        if !self.has_index {
+            // the classic index, with the template's style.css
+            return Layout::Classic.render_index(docfiles);
+        }
+        let mut context = Context::new();
+        context.insert("root", "");
+        context.insert("css_path", "style.css");
+        context.insert("docfiles", &docfiles_context(docfiles));
+        context.insert("search_box", &search::search_box("").into_string());
+        context.insert("tree", &index::tree(docfiles).into_string());
+        self.render(INDEX_TEMPLATE, &context)
+    }
+}
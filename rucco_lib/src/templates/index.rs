@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use maud::Markup;
+
+/// The documented files, as a directory tree.
+/// Directories and files are sorted by name thanks to the BTreeMaps.
+#[derive(Default)]
+struct Tree {
+    dirs: BTreeMap<String, Tree>,
+    files: BTreeMap<String, String> // label -> href
+}
+
+impl Tree {
+    fn insert(&mut self, docfile: &PathBuf) {
+        let href = docfile.to_str().expect("failed to convert doc file path to string");
+        let mut names: Vec<String> = docfile.components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => name.to_str().map(str::to_owned),
+                _ => None
+            })
+            .collect();
+        if let Some(file) = names.pop() {
+            // "main.rs.html" -> "main.rs"
+            let label = Path::new(&file).file_stem()
+                .and_then(OsStr::to_str)
+                .unwrap_or(file.as_str())
+                .to_owned();
+            let mut node = self;
+            for dir in names {
+                node = node.dirs.entry(dir).or_insert_with(Tree::default);
+            }
+            node.files.insert(label, href.to_owned());
+        }
+    }
+}
+
+/// `docfile` is relative to the page, so we drop the leading "../" and the
+/// output extension (".html", ".md") to get back the source path.
+pub fn docfile_label(docfile: &PathBuf) -> String {
+    let source_path: PathBuf = docfile.components()
+        .filter(|c| c != &Component::ParentDir && c != &Component::CurDir)
+        .collect();
+    source_path.with_extension("").to_str()
+        .expect("failed to convert doc file path to string")
+        .to_owned()
+}
+
+fn render_tree(tree: &Tree) -> Markup {
+    html! [
+        ul.tree {
+            @for (name, subtree) in &tree.dirs {
+                li.dir {
+                    span.dir { (name) "/" }
+                    (render_tree(subtree))
+                }
+            }
+            @for (label, href) in &tree.files {
+                li.file {
+                    a.source href=(href) { (label) }
+                }
+            }
+        }
+    ]
+}
+
+fn markdown_tree(tree: &Tree, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    for (name, subtree) in &tree.dirs {
+        out.push_str(&format!("{}- {}/\n", indent, name));
+        markdown_tree(subtree, depth + 1, out);
+    }
+    for (label, href) in &tree.files {
+        out.push_str(&format!("{}- [{}]({})\n", indent, label, href));
+    }
+}
+
+/// Same as `tree`, as a markdown list.
+pub fn markdown(docfiles: &[PathBuf]) -> String {
+    let mut tree = Tree::default();
+    for docfile in docfiles {
+        tree.insert(docfile);
+    }
+    let mut out = String::new();
+    markdown_tree(&tree, 0, &mut out);
+    out
+}
+
+/// The documented files as nested lists, for the index pages.
+/// `docfiles` are the generated pages, relative to the output directory
+/// (where the index itself is written).
+pub fn tree(docfiles: &[PathBuf]) -> Markup {
+    let mut tree = Tree::default();
+    for docfile in docfiles {
+        tree.insert(docfile);
+    }
+    render_tree(&tree)
+}
@@ -0,0 +1,127 @@
+use segment::RenderedSegment;
+use std::path::{Path,PathBuf};
+use maud::{Markup, PreEscaped};
+use templates::{index, search, toc, Page};
+
+/// normalize.css ships in the same resource pack as style.css.
+fn normalize_css_path(css_path: &str) -> String {
+    Path::new(css_path).with_file_name("public/stylesheets/normalize.css")
+        .to_str()
+        .expect("failed to convert css path to string")
+        .to_owned()
+}
+
+/// Every segment gets its anchor (links and search results point to docs and
+/// code too), the page flowing around them.
+fn render_segment(segment: &RenderedSegment, anchor: &str) -> Markup {
+    html! [
+        span id=(anchor) {}
+        @match segment {
+            &RenderedSegment::Title((_, ref html)) => (PreEscaped(html)),
+            &RenderedSegment::Doc(ref doc) => (PreEscaped(doc)),
+            &RenderedSegment::Code((ref code, _, _)) => {
+                @if !code.trim().is_empty() {
+                    (PreEscaped(code))
+                }
+            }
+        }
+    ]
+}
+
+pub fn render(page: &Page) -> String
+{
+    let (docfiles, segments) = (page.docfiles.iter(), page.segments.iter());
+    let source_path = page.source_path;
+    let css_path = page.css_path();
+    let css_path = css_path.as_str();
+    let anchors = page.anchors();
+    let outline = page.outline(&anchors);
+    let mut segments = segments.zip(anchors.iter()).peekable();
+    // a leading title goes in the header, in place of the file path
+    let global_title: Option<(&RenderedSegment, &String)> =
+        if let Some(&(&RenderedSegment::Title(_), _)) = segments.peek() {
+            segments.next()
+        } else {
+            None
+        };
+    let title_to_use: String =
+        if let Some((&RenderedSegment::Title((_, ref t)), _)) = global_title {
+            t.as_str().to_owned()
+        } else {
+            source_path.to_str()
+                .expect("failed to convert file path to string")
+                .to_owned()
+        };
+
+    let docfiles_count = docfiles.clone().count();
+
+    html! [
+        head {
+            title { (title_to_use) }
+            meta http-equiv="content-type" content="text/html; charset=UTF-8" {}
+            link rel="stylesheet" media="all" href=(normalize_css_path(css_path)) {}
+            link rel="stylesheet" media="all" href=(css_path) {}
+            @if let Some(highlight_css_path) = page.highlight_css_path() {
+                link rel="stylesheet" media="all" href=(highlight_css_path) {}
+            }
+        }
+        body {
+            (search::search_box(page.root))
+            div.container {
+                div.page {
+                    div.header {
+                        @if let Some((title, anchor)) = global_title {
+                            (render_segment(title, anchor))
+                        } @else {
+                            h1 { (title_to_use) }
+                        }
+                        @if docfiles_count > 1 {
+                            div.toc {
+                                h3 { "Table of Contents" }
+                                ol {
+                                    @for docfile in docfiles {
+                                        li {
+                                            a.source href=(docfile.to_str().unwrap()) {
+                                                (index::docfile_label(docfile))
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    (toc::toc(&outline))
+                    @for (segment, anchor) in segments {
+                        (render_segment(segment, anchor))
+                    }
+                    div.fleur { "h" }
+                }
+            }
+        }
+    ].into_string()
+}
+
+/// The index page, listing every documented file.
+pub fn render_index<'a>(css_path: &'a str, docfiles: &'a [PathBuf]) -> String
+{
+    html! [
+        head {
+            title { "Index" }
+            meta http-equiv="content-type" content="text/html; charset=UTF-8" {}
+            link rel="stylesheet" media="all" href=(normalize_css_path(css_path)) {}
+            link rel="stylesheet" media="all" href=(css_path) {}
+        }
+        body {
+            (search::search_box(""))
+            div.container {
+                div.page {
+                    div.header {
+                        h1 { "Index" }
+                    }
+                    (index::tree(docfiles))
+                    div.fleur { "h" }
+                }
+            }
+        }
+    ].into_string()
+}
@@ -1,4 +1,137 @@
 pub mod classic;
-//pub mod linear;
-//pub mod parallel;
-//pub mod plain_markdown;
+pub mod index;
+pub mod linear;
+pub mod parallel;
+pub mod plain_markdown;
+pub mod search;
+pub mod toc;
+
+pub mod custom;
+
+use highlight;
+use outline;
+use outline::Heading;
+use segment::Segment;
+use std::path::{Path,PathBuf};
+
+/// Everything a template needs to know to render a documented file.
+pub struct Page<'a> {
+    /// path from the page back to the output directory ("../../")
+    pub root: &'a str,
+    /// the generated pages, relative to this one
+    pub docfiles: &'a [PathBuf],
+    pub source_path: &'a Path,
+    /// highlight extension of the code ("rs" for "main.rs.md"), a fence tag
+    pub language: &'a str,
+    /// rendered for html templates, raw otherwise
+    pub segments: &'a [Segment],
+    /// deepest title level showing in the table of contents
+    pub toc_depth: u8,
+    /// code is highlighted with classes, styled by `highlight.css`
+    pub highlight_css: bool
+}
+
+impl<'a> Page<'a> {
+    pub fn css_path(&self) -> String {
+        [self.root, "style.css"].concat()
+    }
+
+    pub fn highlight_css_path(&self) -> Option<String> {
+        if self.highlight_css {
+            Some([self.root, highlight::CSS_FILE].concat())
+        } else {
+            None
+        }
+    }
+
+    /// one per segment
+    pub fn anchors(&self) -> Vec<String> {
+        outline::anchors(self.segments)
+    }
+
+    pub fn outline(&self, anchors: &[String]) -> Vec<Heading> {
+        outline::outline(self.segments, anchors, self.toc_depth)
+    }
+}
+
+/// Puts documented files (and the index listing them) into pages.
+/// Implemented by the built-in layouts and by user template directories.
+pub trait Template: Sync {
+    /// Html templates get rendered segments, the others get the raw ones.
+    fn is_html(&self) -> bool;
+    /// Extension of the generated pages.
+    fn extension(&self) -> &str;
+    fn render_page(&self, page: &Page) -> Option<String>;
+    /// `docfiles` are relative to the output directory, where the index goes.
+    fn render_index(&self, docfiles: &[PathBuf]) -> Option<String>;
+}
+
+/// The page layouts rucco knows about, selected by name from the command line
+/// or the ruccofile.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Layout {
+    Classic,
+    Linear,
+    Parallel,
+    PlainMarkdown
+}
+
+impl Layout {
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "classic" => Some(Layout::Classic),
+            "linear" => Some(Layout::Linear),
+            "parallel" => Some(Layout::Parallel),
+            "plain_markdown" => Some(Layout::PlainMarkdown),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Layout::Classic => "classic",
+            Layout::Linear => "linear",
+            Layout::Parallel => "parallel",
+            Layout::PlainMarkdown => "plain_markdown"
+        }
+    }
+
+    /// The resource tarball (css, fonts...) embedded in the rucco binary that
+    /// goes with the layout, if any.
+    pub fn resources(&self) -> Option<&'static str> {
+        match *self {
+            Layout::Classic => Some("classic.tar"),
+            Layout::Linear => Some("linear.tar"),
+            Layout::Parallel => Some("parallel.tar"),
+            Layout::PlainMarkdown => None
+        }
+    }
+}
+
+impl Template for Layout {
+    fn is_html(&self) -> bool {
+        *self != Layout::PlainMarkdown
+    }
+
+    fn extension(&self) -> &str {
+        if self.is_html() { "html" } else { "md" }
+    }
+
+    fn render_page(&self, page: &Page) -> Option<String> {
+        Some(match *self {
+            Layout::Classic => classic::render(page),
+            Layout::Linear => linear::render(page),
+            Layout::Parallel => parallel::render(page),
+            Layout::PlainMarkdown => plain_markdown::render(page)
+        })
+    }
+
+    fn render_index(&self, docfiles: &[PathBuf]) -> Option<String> {
+        Some(match *self {
+            Layout::Classic => classic::render_index("style.css", docfiles),
+            Layout::Linear => linear::render_index("style.css", docfiles),
+            Layout::Parallel => parallel::render_index("style.css", docfiles),
+            Layout::PlainMarkdown => plain_markdown::render_index("style.css", docfiles)
+        })
+    }
+}
@@ -0,0 +1,189 @@
+use std;
+use segment::RenderedSegment;
+use std::path::PathBuf;
+use maud::PreEscaped;
+use outline::count;
+use templates::{index, search, toc, Page};
+
+/// A row of the page: the documentation on the left, the code it talks about
+/// on the right. Both sides are in the same row so they stay aligned (and
+/// scroll together). Every segment of the row keeps its anchor, the row
+/// having the first one.
+struct Section<'a> {
+    /// anchor of the first segment of the section
+    anchor: &'a str,
+    docs: Vec<(&'a str, &'a RenderedSegment)>,
+    code: Option<(&'a str, &'a str)>
+}
+
+impl<'a> Section<'a> {
+    fn new(anchor: &'a str) -> Section<'a> {
+        Section { anchor: anchor, docs: vec![], code: None }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.docs.is_empty() && self.code.is_none()
+    }
+
+    /// the pilcrow is placed differently when the section starts with a heading
+    fn pilwrap_class(&self) -> String {
+        match self.docs.first() {
+            Some(&(_, &RenderedSegment::Title((level, _)))) => format!("pilwrap for-h{}", level),
+            _ => "pilwrap".to_owned()
+        }
+    }
+}
+
+/// Documentation segments are grouped with the code segment following them.
+fn sections<'a>(segments: &'a [RenderedSegment], anchors: &'a [String]) -> Vec<Section<'a>> {
+    let mut sections = vec![];
+    let mut cur = Section::new("");
+    for (segment, anchor) in segments.iter().zip(anchors.iter().map(String::as_str)) {
+        if cur.code.is_some() {
+            sections.push(std::mem::replace(&mut cur, Section::new(anchor)));
+        }
+        if cur.is_empty() {
+            cur.anchor = anchor;
+        }
+        match segment {
+            &RenderedSegment::Code((ref code, _, _)) => cur.code = Some((anchor, code.as_str())),
+            doc => cur.docs.push((anchor, doc))
+        }
+    }
+    if !cur.is_empty() {
+        sections.push(cur);
+    }
+    sections
+}
+
+pub fn render(page: &Page) -> String
+{
+    let (docfiles, segments) = (page.docfiles.iter(), page.segments.iter());
+    let source_path = page.source_path;
+    let css_path = page.css_path();
+    let css_path = css_path.as_str();
+    let mut peek_segments = segments.clone().peekable();
+    let (has_global_title, title_to_use): (bool, String) =
+        if let Some(&&RenderedSegment::Title((_, ref t))) = peek_segments.peek() {
+            (true, t.as_str().to_owned())
+        } else {
+            (false, source_path.to_str()
+             .expect("failed to convert file path to string")
+             .to_owned())
+        };
+
+    let docfiles_count = docfiles.clone().count();
+    let anchors = page.anchors();
+    let outline = page.outline(&anchors);
+
+    html! [
+        head {
+            title { (title_to_use) }
+            meta http-equiv="content-type" content="text/html; charset=UTF-8" {}
+            meta name="viewport" content="width=device-width, target-densitydpi=160dpi, initial-scale=1.0, maximum-scale=1.0, user-scalable=0" {}
+            link rel="stylesheet" media="all" href=(css_path) {}
+            @if let Some(highlight_css_path) = page.highlight_css_path() {
+                link rel="stylesheet" media="all" href=(highlight_css_path) {}
+            }
+        }
+        body {
+            div#container {
+                div#background {}
+                (search::search_box(page.root))
+                @if docfiles_count > 1 {
+                    ul#jump_to {
+                        li {
+                            a.large href="javascript:void(0)" { "Jump To …" }
+                            a.small href="javascript:void(0)" { "+" }
+                            div#jump_wrapper {
+                                div#jump_page_wrapper {
+                                    div#jump_page {
+                                        @for docfile in docfiles {
+                                            a.source href=(docfile.to_str().unwrap()) {
+                                                (index::docfile_label(docfile))
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                ul.sections {
+                    @if !has_global_title {
+                        li#title {
+                            div.annotation {
+                                h1 { (title_to_use) }
+                            }
+                        }
+                    }
+                    @if count(&outline) > 1 {
+                        li#toc {
+                            div.annotation {
+                                (toc::toc(&outline))
+                            }
+                        }
+                    }
+                    @for section in sections(page.segments, &anchors) {
+                        li id=(section.anchor) {
+                            div.annotation {
+                                div.sticky {
+                                    div class=(section.pilwrap_class()) {
+                                        a.pilcrow href={ "#" (section.anchor) } { "¶" }
+                                    }
+                                    @for &(anchor, doc) in &section.docs {
+                                        @if anchor != section.anchor {
+                                            span id=(anchor) {}
+                                        }
+                                        @match doc {
+                                            &RenderedSegment::Title((_, ref html)) => (PreEscaped(html)),
+                                            &RenderedSegment::Doc(ref html) => (PreEscaped(html)),
+                                            &RenderedSegment::Code(_) => {}
+                                        }
+                                    }
+                                }
+                            }
+                            @if let Some((anchor, code)) = section.code {
+                                @if anchor != section.anchor {
+                                    span id=(anchor) {}
+                                }
+                                @if !code.trim().is_empty() {
+                                    div.content {
+                                        (PreEscaped(code))
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    ].into_string()
+}
+
+/// The index page, listing every documented file.
+pub fn render_index<'a>(css_path: &'a str, docfiles: &'a [PathBuf]) -> String
+{
+    html! [
+        head {
+            title { "Index" }
+            meta http-equiv="content-type" content="text/html; charset=UTF-8" {}
+            meta name="viewport" content="width=device-width, target-densitydpi=160dpi, initial-scale=1.0, maximum-scale=1.0, user-scalable=0" {}
+            link rel="stylesheet" media="all" href=(css_path) {}
+        }
+        body {
+            div#container {
+                div#background {}
+                (search::search_box(""))
+                ul.sections {
+                    li#title {
+                        div.annotation {
+                            h1 { "Index" }
+                            (index::tree(docfiles))
+                        }
+                    }
+                }
+            }
+        }
+    ].into_string()
+}
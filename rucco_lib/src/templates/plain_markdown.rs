@@ -0,0 +1,101 @@
+use std;
+use segment::{Annotation, Segment};
+use std::path::PathBuf;
+use templates::{index, toc, Page};
+
+/// The fence must be longer than any run of backticks in the code, or the code
+/// block would be closed early.
+fn fence(code: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in code.chars() {
+        if c == '`' {
+            current += 1;
+            longest = std::cmp::max(longest, current);
+        } else {
+            current = 0;
+        }
+    }
+    "`".repeat(std::cmp::max(3, longest + 1))
+}
+
+/// Annotations follow their code block, as a list: "- `let x = 1;`: moo".
+fn notes(code: &str, annotations: &[Annotation]) -> String {
+    let lines: Vec<&str> = code.lines().collect();
+    let mut notes = String::new();
+    for &(line, ref note) in annotations {
+        match lines.get(line).map(|l| l.trim()) {
+            Some(l) if !l.is_empty() && !l.contains('`') => notes.push_str(&format!("- `{}`: {}\n", l, note)),
+            _ => notes.push_str(&format!("- {}\n", note))
+        }
+    }
+    if !notes.is_empty() {
+        notes.push('\n');
+    }
+    notes
+}
+
+/// Works from the raw segments: doc text is passed through verbatim, and code
+/// gets fenced with the page language as tag.
+pub fn render(page: &Page) -> String
+{
+    let (docfiles, segments) = (page.docfiles.iter(), page.segments.iter());
+    let source_path = page.source_path;
+    let language = page.language;
+    let mut peek_segments = segments.clone().peekable();
+    let has_global_title = if let Some(&&Segment::Title(_)) = peek_segments.peek() {
+        true
+    } else {
+        false
+    };
+
+    // right after the title
+    let mut toc = toc::markdown(&page.outline(&page.anchors()));
+    if !toc.is_empty() {
+        toc.push('\n');
+    }
+
+    let mut out = String::new();
+    if !has_global_title {
+        out.push_str(&format!("# {}\n\n", source_path.display()));
+        out.push_str(&toc);
+    }
+    for (i, segment) in segments.enumerate() {
+        match segment {
+            &Segment::Title((_, ref text)) | &Segment::Doc(ref text) => {
+                out.push_str(text);
+                if !text.ends_with('\n') {
+                    out.push('\n');
+                }
+                if i == 0 && has_global_title {
+                    out.push('\n');
+                    out.push_str(&toc);
+                }
+            },
+            &Segment::Code((ref code, ref annotations, _)) => {
+                let code = code.trim_end_matches('\n');
+                if !code.trim().is_empty() {
+                    let fence = fence(code);
+                    out.push_str(&format!("\n{}{}\n{}\n{}\n\n", fence, language, code, fence));
+                    out.push_str(&notes(code, annotations));
+                }
+            }
+        }
+    }
+
+    if docfiles.clone().count() > 1 {
+        out.push_str("\n---\n\n");
+        for docfile in docfiles {
+            out.push_str(&format!("- [{}]({})\n",
+                                  index::docfile_label(docfile),
+                                  docfile.to_str().expect("failed to convert doc file path to string")));
+        }
+    }
+    out
+}
+
+/// The index page, listing every documented file.
+pub fn render_index<'a>(_css_path: &'a str, docfiles: &'a [PathBuf]) -> String
+{
+    ["# Index\n\n", index::markdown(docfiles).as_str()].concat()
+}
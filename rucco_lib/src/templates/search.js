@@ -0,0 +1,81 @@
+// Rucco search box: loads `search-index.js` (see rucco_lib::search) on first
+// use and lists the sections whose title and text contain every search term.
+(function () {
+  var box = document.getElementById("search");
+  if (!box) { return; }
+  var root = box.getAttribute("data-root");
+  var input = document.getElementById("search_input");
+  var results = document.getElementById("search_results");
+  var index = null;
+  var loading = false;
+  var MAX_RESULTS = 20;
+
+  function unavailable() {
+    results.innerHTML = "";
+    var li = document.createElement("li");
+    li.className = "unavailable";
+    li.textContent = "Search unavailable: could not load the search index.";
+    results.appendChild(li);
+  }
+
+  // a script and not an XHR, for pages opened from file://
+  function load(then) {
+    if (index) { return then(); }
+    if (loading) { return; }
+    loading = true;
+    var script = document.createElement("script");
+    script.src = root + "search-index.js";
+    script.onload = function () {
+      loading = false;
+      index = window.rucco_search_index || null;
+      if (index) { then(); } else { unavailable(); }
+    };
+    script.onerror = function () {
+      loading = false;
+      document.head.removeChild(script);
+      unavailable();
+    };
+    document.head.appendChild(script);
+  }
+
+  function snippet(text, term) {
+    var at = text.toLowerCase().indexOf(term);
+    var start = Math.max(0, at - 40);
+    return (start > 0 ? "…" : "") + text.substr(start, 120) + (start + 120 < text.length ? "…" : "");
+  }
+
+  function search() {
+    var terms = input.value.toLowerCase().split(/\s+/).filter(function (t) { return t.length > 0; });
+    results.innerHTML = "";
+    if (terms.length === 0) { return; }
+    var hits = [];
+    index.entries.forEach(function (entry) {
+      var title = entry[2].toLowerCase();
+      var haystack = title + " " + entry[3].toLowerCase();
+      if (terms.every(function (t) { return haystack.indexOf(t) >= 0; })) {
+        var score = terms.filter(function (t) { return title.indexOf(t) >= 0; }).length;
+        hits.push({ entry: entry, score: score });
+      }
+    });
+    hits.sort(function (a, b) { return b.score - a.score; });
+    hits.slice(0, MAX_RESULTS).forEach(function (hit) {
+      var entry = hit.entry;
+      var li = document.createElement("li");
+      var a = document.createElement("a");
+      a.href = root + index.pages[entry[0]] + "#" + entry[1];
+      a.textContent = entry[2];
+      var page = document.createElement("span");
+      page.className = "page";
+      page.textContent = index.pages[entry[0]].replace(/\.html$/, "");
+      var text = document.createElement("span");
+      text.className = "text";
+      text.textContent = snippet(entry[3], terms[0]);
+      li.appendChild(a);
+      li.appendChild(page);
+      li.appendChild(text);
+      results.appendChild(li);
+    });
+  }
+
+  input.addEventListener("input", function () { load(search); });
+})();
@@ -0,0 +1,21 @@
+use maud::Markup;
+
+/// No CDN: the script and its style are embedded in the rucco binary and
+/// written once to the output directory, next to `style.css`.
+pub const SCRIPT_FILE: &'static str = "search.js";
+pub const CSS_FILE: &'static str = "search.css";
+pub const SCRIPT: &'static str = include_str!("search.js");
+pub const CSS: &'static str = include_str!("search.css");
+
+/// `root` is the path from the page back to the output directory, where the
+/// search assets and `search-index.js` are written.
+pub fn search_box(root: &str) -> Markup {
+    html! [
+        link rel="stylesheet" media="all" href={ (root) (CSS_FILE) } {}
+        div#search data-root=(root) {
+            input#search_input type="search" placeholder="Search…" autocomplete="off" {}
+            ul#search_results {}
+        }
+        script src={ (root) (SCRIPT_FILE) } {}
+    ]
+}
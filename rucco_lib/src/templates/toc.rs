@@ -0,0 +1,44 @@
+use maud::Markup;
+use outline::{count, Heading};
+
+fn render_headings(headings: &[Heading]) -> Markup {
+    html! [
+        ol {
+            @for heading in headings {
+                li {
+                    a href={ "#" (heading.anchor) } { (heading.title) }
+                    @if !heading.children.is_empty() {
+                        (render_headings(&heading.children))
+                    }
+                }
+            }
+        }
+    ]
+}
+
+/// The table of contents of a page, nothing if there are less than two
+/// headings to show.
+pub fn toc(headings: &[Heading]) -> Markup {
+    html! [
+        @if count(headings) > 1 {
+            nav.outline {
+                (render_headings(headings))
+            }
+        }
+    ]
+}
+
+/// Same as `toc`, as a markdown list.
+pub fn markdown(headings: &[Heading]) -> String {
+    fn push_headings(headings: &[Heading], depth: usize, out: &mut String) {
+        for heading in headings {
+            out.push_str(&format!("{}- [{}](#{})\n", "  ".repeat(depth), heading.title, heading.anchor));
+            push_headings(&heading.children, depth + 1, out);
+        }
+    }
+    let mut out = String::new();
+    if count(headings) > 1 {
+        push_headings(headings, 0, &mut out);
+    }
+    out
+}
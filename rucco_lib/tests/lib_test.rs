@@ -4,9 +4,11 @@ extern crate toml;
 
 use std::collections::BTreeMap;
 use rucco_lib::*;
-use rucco_lib::languages::compute_regex;
+use rucco_lib::languages::compute_syntax;
+use rucco_lib::lexer::{tokenize, Token};
+use rucco_lib::segment::Segment;
 
-const C_SAMPLE: &'static str = r"
+const C_SAMPLE: &'static str = r#"
 int a = 12;
 
 /* first block */
@@ -27,13 +29,14 @@ momo
 */
 
 char* gororo = 'm'; // ignore me!!!
+char* url = "http://x /* not a comment */"; char c = '/';
 
 /*
  * # moo
  * yo
 */
 
-";
+"#;
 
 fn c_language() -> toml::Value {
     let mut c: toml::Table = BTreeMap::new();
@@ -42,25 +45,368 @@ fn c_language() -> toml::Value {
     c.insert("multiline_header".to_string(), toml::Value::String(r"/\*+".to_string()));
     c.insert("multiline_footer".to_string(), toml::Value::String(r"\*+/".to_string()));
     c.insert("multiline_margin".to_string(), toml::Value::String(r"\*+".to_string()));
+    c.insert("strings".to_string(), toml::Value::Array(vec![toml::Value::String("\"".to_string())]));
+    c.insert("chars".to_string(), toml::Value::String("'".to_string()));
     toml::Value::Table(c)
 }
 
 #[test]
-fn regex_parse_ok() {
+fn lexer_ok() {
     env_logger::init();
-    let r = compute_regex(&c_language()).expect("failed to create c language regex");
-    for capture in r.captures_iter(C_SAMPLE) {
-        println!("regex_parse_ok: {:?}", capture);
+    let syntax = compute_syntax(&c_language()).expect("failed to create c language syntax");
+    let tokens: Vec<Token> = tokenize(&syntax, C_SAMPLE).collect();
+    for token in &tokens {
+        println!("lexer_ok: {:?}", token);
     };
+    assert!(tokens.contains(&Token::Code("char* url = \"http://x /* not a comment */\"; char c = '/';")));
+    assert!(tokens.contains(&Token::LineComment { text: "// ignore me!!!", body: " ignore me!!!" }));
 }
 
 #[test]
 fn segments_ok() {
     env_logger::init();
-    let r = compute_regex(&c_language()).expect("failed to create c language regex");
-    for capture in rucco_lib::segment::extract_segments(&r, C_SAMPLE) {
-        println!("segments_ok: {:?}", capture);
+    let syntax = compute_syntax(&c_language()).expect("failed to create c language syntax");
+    let segments: Vec<Segment> = rucco_lib::segment::extract_segments(&syntax, C_SAMPLE).collect();
+    for segment in &segments {
+        println!("segments_ok: {:?}", segment);
     };
+    match segments[0] {
+        Segment::Code((ref code, _, _)) => assert_eq!(code, "int a = 12;\n"),
+        _ => panic!("expected a code segment")
+    }
+    match segments[3] {
+        Segment::Doc(ref doc) => assert_eq!(doc, "moomfomfomfoof\n\nkakarot\n- zozo\n- dodo\n\n"),
+        _ => panic!("expected a doc segment")
+    }
+    assert!(segments.iter().any(|s| match s {
+        &Segment::Code((ref code, _, _)) => code.contains("/* not a comment */"),
+        _ => false
+    }));
+}
+
+fn code_lines(segments: &[Segment]) -> Vec<(&str, &[usize])> {
+    segments.iter().filter_map(|s| match s {
+        &Segment::Code((ref c, _, ref lines)) => Some((c.as_str(), lines.as_slice())),
+        _ => None
+    }).collect()
+}
+
+#[test]
+fn line_numbers_ok() {
+    let syntax = compute_syntax(&c_language()).expect("failed to create c language syntax");
+    let segments: Vec<Segment> = rucco_lib::segment::extract_segments(&syntax, C_SAMPLE).collect();
+    let lines = code_lines(&segments);
+    assert_eq!(lines[0], ("int a = 12;\n", &[2][..]));
+    assert_eq!(lines[1], ("int b = 77;\n", &[14][..]));
+    assert_eq!(lines[2].1, &[21, 22][..]);
+
+    let markdown: toml::Value = "singleline = \"\"\nliterate = true".parse().expect("malformed language");
+    let syntax = compute_syntax(&markdown).expect("failed to create markdown syntax");
+    let source = "# Design\n\nprose\n\n```rust\nfn main() {}\n```\n\n    let x = 1;\n";
+    let segments: Vec<Segment> = rucco_lib::segment::extract_segments(&syntax, source).collect();
+    assert_eq!(code_lines(&segments), vec![("fn main() {}\n\nlet x = 1;\n", &[6, 8, 9][..])]);
+
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("c".to_string(), c_language());
+    let mut langs = Languages::new(raw);
+    let highlighting = Highlighting::defaults().with_line_numbers(true);
+    let segments = render_segments(&highlighting, &Markdown::defaults(), &mut langs, "c", "\nint a = 12;\n").expect("failed to render");
+    match segments[0] {
+        Segment::Code((ref html, _, _)) => {
+            assert!(html.contains("id=\"L2\""), "{}", html);
+            assert!(html.contains("data-line=\"2\""), "{}", html);
+        },
+        _ => panic!("expected a code segment")
+    }
+}
+
+/// The languages of the Ruccofile shipped with rucco.
+fn shipped_languages() -> Languages {
+    let ruccofile: toml::Value = include_str!("../../resources/Ruccofile.toml").parse()
+        .expect("malformed shipped Ruccofile");
+    let languages = ruccofile.get("languages").and_then(toml::Value::as_table)
+        .expect("no languages in the shipped Ruccofile");
+    Languages::new(languages.clone())
+}
+
+fn nested_segments(extension: &str, source: &str) -> Vec<Segment> {
+    raw_segments(&mut shipped_languages(), extension, source).expect("unknown language")
+}
+
+fn assert_doc_then_code(segments: &[Segment], doc: &str, code: &str) {
+    assert_eq!(segments.len(), 2, "{:?}", segments);
+    match segments[0] {
+        Segment::Doc(ref d) => assert_eq!(d, doc),
+        _ => panic!("expected a doc segment")
+    }
+    match segments[1] {
+        Segment::Code((ref c, _, _)) => assert_eq!(c, code),
+        _ => panic!("expected a code segment")
+    }
+}
+
+#[test]
+fn nested_rust_ok() {
+    let segments = nested_segments("rs", "/* outer /* inner */ still doc */\nlet s = \"*/\";\n");
+    assert_doc_then_code(&segments, "outer /* inner */ still doc\n", "let s = \"*/\";\n");
+    let segments = nested_segments("rs", "/*! outer /** inner */ still doc */\nfn main() {}\n");
+    assert_doc_then_code(&segments, "outer /** inner */ still doc\n", "fn main() {}\n");
+    // "/**/" is an empty comment, not an opener
+    let segments = nested_segments("rs", "/* a /**/ b */\nfn main() {}\n");
+    assert_doc_then_code(&segments, "a /**/ b\n", "fn main() {}\n");
+    let segments = nested_segments("rs", "/* /**/ */\nfn main() {}\n");
+    assert_doc_then_code(&segments, "/**/\n", "fn main() {}\n");
+}
+
+#[test]
+fn nested_haskell_ok() {
+    let segments = nested_segments("hs", "{- outer\n   {- inner -}\n   still doc\n-}\nmain = pure ()\n");
+    assert_doc_then_code(&segments, "outer\n{- inner -}\nstill doc\n", "main = pure ()\n");
+    let segments = nested_segments("hs", "{-| outer {- inner -} still doc -}\nmain = pure ()\n");
+    assert_doc_then_code(&segments, "outer {- inner -} still doc\n", "main = pure ()\n");
+}
+
+#[test]
+fn nested_ocaml_ok() {
+    let segments = nested_segments("ml", "(** outer (* inner (* innermost *) *) still doc *)\nlet x = 1\n");
+    assert_doc_then_code(&segments, "outer (* inner (* innermost *) *) still doc\n", "let x = 1\n");
+}
+
+#[test]
+fn nested_swift_ok() {
+    let segments = nested_segments("swift", "/** outer /* inner */ still doc */\nlet s = \"*/\"\n");
+    assert_doc_then_code(&segments, "outer /* inner */ still doc\n", "let s = \"*/\"\n");
+}
+
+#[test]
+fn nested_scala_ok() {
+    let segments = nested_segments("scala", "/* outer /* inner /* innermost */ */ still doc */\nval s = \"*/\"\n");
+    assert_doc_then_code(&segments, "outer /* inner /* innermost */ */ still doc\n", "val s = \"*/\"\n");
+}
+
+fn segment_kinds(segments: &[Segment]) -> Vec<(&'static str, &str)> {
+    segments.iter().map(|s| match s {
+        &Segment::Title((_, ref t)) => ("title", t.as_str()),
+        &Segment::Doc(ref d) => ("doc", d.as_str()),
+        &Segment::Code((ref c, _, _)) => ("code", c.as_str())
+    }).collect()
+}
+
+#[test]
+fn literate_ok() {
+    use std::path::Path;
+    let raw: toml::Value = r#"
+[rs]
+singleline = "//"
+aliases = ["rust"]
+[md]
+singleline = ""
+literate_suffix = true
+[lhs]
+literate = "bird"
+highlight = "hs"
+"#.parse().expect("malformed languages");
+    let mut langs = Languages::new(raw.as_table().expect("languages is not a table").clone());
+
+    let extension = langs.language_of(Path::new("doc/design.rs.md"), None).expect("no extension");
+    assert_eq!(extension, "rs.md");
+    assert_eq!(langs.highlight_extension(&extension), "rs");
+    assert_eq!(langs.language_of(Path::new("README.md"), None).expect("no extension"), "md");
+    let segments = raw_segments(&mut langs, &extension,
+                                "# Design\n\nprose\n\n```rust\nfn main() {}\n```\n\n    let x = 1;\n")
+        .expect("failed to extract literate markdown");
+    assert_eq!(segment_kinds(&segments), vec![("title", "# Design\n"),
+                                              ("doc", "prose\n\n"),
+                                              ("code", "fn main() {}\n\nlet x = 1;\n")]);
+
+    // blocks in another language are examples, in the doc
+    let segments = raw_segments(&mut langs, &extension,
+                                "Build:\n\n```sh\n# release\ncargo build\n```\n\n```rs\nfn main() {}\n```\n")
+        .expect("failed to extract literate markdown");
+    assert_eq!(segment_kinds(&segments), vec![("doc", "Build:\n\n```sh\n# release\ncargo build\n```\n\n"),
+                                              ("code", "fn main() {}\n")]);
+
+    assert_eq!(langs.highlight_extension("lhs"), "hs");
+    let segments = raw_segments(&mut langs, "lhs", "Intro\n\n> main = pure ()\n")
+        .expect("failed to extract literate haskell");
+    assert_eq!(segment_kinds(&segments), vec![("doc", "Intro\n\n"), ("code", "main = pure ()\n")]);
+}
+
+#[test]
+fn detection_ok() {
+    use std::path::Path;
+    let raw: toml::Value = r##"
+[make]
+singleline = "#"
+filenames = ["Makefile"]
+globs = ["*.mk", "build/**/*.inc"]
+shebangs = ["make"]
+syntax = "Makefile"
+[py]
+singleline = "#+"
+shebangs = ["python"]
+[sh]
+singleline = "#"
+filenames = [".bashrc"]
+shebangs = ["sh", "bash"]
+"##.parse().expect("malformed languages");
+    let mut langs = Languages::new(raw.as_table().expect("languages is not a table").clone());
+
+    let language = |path: &str, first_line: Option<&str>| langs.language_of(Path::new(path), first_line);
+    assert_eq!(language("src/Makefile", None), Some("make".to_string()));
+    assert_eq!(language("rules/common.mk", None), Some("make".to_string()));
+    assert_eq!(language("build/x/y/defs.inc", None), Some("make".to_string()));
+    assert_eq!(language("other/defs.inc", None), None);
+    assert_eq!(language("home/.bashrc", None), Some("sh".to_string()));
+    assert_eq!(language("tool.py", Some("#!/bin/sh\n")), Some("py".to_string()));
+    assert_eq!(language("bin/tool", None), None);
+    assert_eq!(language("bin/tool", Some("#!/usr/bin/env python3\n")), Some("py".to_string()));
+    assert_eq!(language("bin/tool", Some("#!/usr/bin/env -S bash -e\n")), Some("sh".to_string()));
+    assert_eq!(language("bin/tool", Some("#!/bin/bash\n")), Some("sh".to_string()));
+    assert_eq!(language("bin/tool", Some("# no shebang\n")), None);
+    assert_eq!(language("image.png", None), None);
+    assert_eq!(langs.highlight_syntax("make"), Some("Makefile".to_string()));
+    assert_eq!(langs.highlight_syntax("py"), None);
+}
+
+#[test]
+fn doc_only_ok() {
+    use rucco_lib::languages::Comments;
+    let raw: toml::Value = r#"
+[rs]
+singleline = "//[/!]?"
+multiline_header = "/\\*+!?"
+multiline_footer = "\\*+/"
+doc_singleline = "//[/!]"
+doc_multiline_header = "/\\*[*!]"
+"#.parse().expect("malformed languages");
+    let raw = raw.as_table().expect("languages is not a table");
+    let source = "//! crate doc\n// TODO: not doc\nfn f() {}\n/** block doc */\n/* plain */\nfn g() {}\n";
+
+    let mut langs = Languages::new(raw.clone()).with_comments(Comments::DocOnly);
+    let segments = raw_segments(&mut langs, "rs", source).expect("failed to extract segments");
+    assert_eq!(segment_kinds(&segments), vec![("doc", "crate doc\n"),
+                                              ("code", "// TODO: not doc\nfn f() {}\n"),
+                                              ("doc", "block doc\n"),
+                                              ("code", "/* plain */\nfn g() {}\n")]);
+
+    let mut langs = Languages::new(raw.clone());
+    let segments = raw_segments(&mut langs, "rs", source).expect("failed to extract segments");
+    assert_eq!(segment_kinds(&segments), vec![("doc", "crate doc\nTODO: not doc\n"),
+                                              ("code", "fn f() {}\n"),
+                                              ("doc", "block doc\nplain\n"),
+                                              ("code", "fn g() {}\n")]);
+}
+
+#[test]
+fn annotations_ok() {
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("c".to_string(), c_language());
+    let source = "int a = 1; // one\nchar* u = \"http://x\";\nint c = 3; // three\n// doc\nint d; //\n";
+
+    let mut langs = Languages::new(raw.clone()).with_annotations(true);
+    let segments = raw_segments(&mut langs, "c", source).expect("failed to extract segments");
+    assert_eq!(segments.len(), 3, "{:?}", segments);
+    match segments[0] {
+        Segment::Code((ref code, ref annotations, _)) => {
+            assert_eq!(code, "int a = 1;\nchar* u = \"http://x\";\nint c = 3;\n");
+            assert_eq!(annotations, &vec![(0, "one".to_string()), (2, "three".to_string())]);
+        },
+        _ => panic!("expected a code segment")
+    }
+    match segments[2] {
+        Segment::Code((ref code, ref annotations, _)) => {
+            assert_eq!(code, "int d; //\n");
+            assert!(annotations.is_empty());
+        },
+        _ => panic!("expected a code segment")
+    }
+
+    let mut langs = Languages::new(raw);
+    let segments = raw_segments(&mut langs, "c", source).expect("failed to extract segments");
+    assert_eq!(segment_kinds(&segments)[0], ("code", "int a = 1; // one\nchar* u = \"http://x\";\nint c = 3; // three\n"));
+}
+
+#[test]
+fn docstrings_ok() {
+    let raw: toml::Value = r##"
+[py]
+singleline = "#+"
+strings = ['"""', "'''", '"', "'"]
+docstrings = ['"""', "'''"]
+docstring_prefix = "[rRuU]?"
+definitions = ['def\s+(?P<name>\w+)', 'class\s+(?P<name>\w+)']
+[ex]
+singleline = "#"
+strings = ['"""', '"']
+docstrings = ['"""']
+docstring_prefix = "@(?:module|type)?doc\\s+"
+docstring_placement = "either"
+definitions = ['defp?\s+(?P<name>\w+)', 'defmodule\s+(?P<name>\w+)']
+[jl]
+singleline = "#"
+strings = ['"""', '"']
+docstrings = ['"""', '"']
+docstring_placement = "preceding"
+definitions = ['function\s+(?P<name>\w+)', '(?P<name>\w+)\([^)]*\)\s*=[^=]']
+"##.parse().expect("malformed languages");
+    let mut langs = Languages::new(raw.as_table().expect("languages is not a table").clone());
+
+    let python = "def f(x):\n    \"\"\"Summary.\n\n    Details.\n    \"\"\"\n    s = \"\"\"not a docstring\"\"\"\n    return x\n";
+    let segments = raw_segments(&mut langs, "py", python).expect("failed to extract python");
+    assert_eq!(segment_kinds(&segments), vec![("code", "def f(x):\n"),
+                                              ("doc", "Summary.\n\nDetails.\n"),
+                                              ("code", "    s = \"\"\"not a docstring\"\"\"\n    return x\n")]);
+
+    let elixir = "defmodule M do\n  @moduledoc \"\"\"\n  The module.\n  \"\"\"\n\n  def f, do: \"\"\"\n  heredoc\n  \"\"\"\nend\n";
+    let segments = raw_segments(&mut langs, "ex", elixir).expect("failed to extract elixir");
+    assert_eq!(segment_kinds(&segments), vec![("code", "defmodule M do\n"),
+                                              ("doc", "The module.\n\n"),
+                                              ("code", "  def f, do: \"\"\"\n  heredoc\n  \"\"\"\nend\n")]);
+
+    let julia = "\"\"\"\n    f(x)\n\nComputes.\n\"\"\"\nf(x) = x\n";
+    let segments = raw_segments(&mut langs, "jl", julia).expect("failed to extract julia");
+    assert_eq!(segment_kinds(&segments), vec![("doc", "    f(x)\n\nComputes.\n"),
+                                              ("code", "f(x) = x\n")]);
+
+    // only where they document a definition
+    let python = "#!/usr/bin/env python\n\"\"\"Module.\"\"\"\ndef f(\n    x,\n):\n    \"\"\"Summary.\"\"\"\n    execute(\n        \"\"\"SELECT 1\"\"\"\n    )\n";
+    let segments = raw_segments(&mut langs, "py", python).expect("failed to extract python");
+    assert_eq!(segment_kinds(&segments), vec![("code", "#!/usr/bin/env python\n"),
+                                              ("doc", "Module.\n"),
+                                              ("code", "def f(\n    x,\n):\n"),
+                                              ("doc", "Summary.\n"),
+                                              ("code", "    execute(\n        \"\"\"SELECT 1\"\"\"\n    )\n")]);
+
+    let elixir = "defmodule M do\n  @doc \"\"\"\n  Doubles.\n  \"\"\"\n  @spec f(integer) :: integer\n  def f(x), do: 2 * x\nend\n";
+    let segments = raw_segments(&mut langs, "ex", elixir).expect("failed to extract elixir");
+    assert_eq!(segment_kinds(&segments)[1], ("doc", "Doubles.\n"));
+
+    let julia = "function f()\n    \"hello\"\nend\n";
+    let segments = raw_segments(&mut langs, "jl", julia).expect("failed to extract julia");
+    assert_eq!(segment_kinds(&segments), vec![("code", "function f()\n    \"hello\"\nend\n")]);
+}
+
+#[test]
+fn tangle_ok() {
+    use rucco_lib::literate::Literate;
+    use rucco_lib::tangle::tangle;
+    let chunks = "# Design\n\n```rust\n<<*>>=\nfn main() {\n    <<body>>\n}\n```\n\nThe body:\n\n\
+                  ```rust\n<<body>>=\nprintln!(\"hi\");\n<<nope>>\n```\n\nunnamed blocks are left out:\n\n    let x = 1;\n";
+    let rust = vec!["rs".to_string(), "rust".to_string()];
+    let (code, problems) = tangle(Literate::Markdown, &rust, chunks);
+    assert_eq!(code, "fn main() {\n    println!(\"hi\");\n}\n");
+    assert_eq!(problems, vec!["undefined chunk: <<nope>>"]);
+
+    // examples in other languages are not code
+    let (code, problems) = tangle(Literate::Markdown, &rust,
+                                  "Run:\n\n```sh\ncargo run\n```\n\n```\nfn main() {}\n```\n\n```rust,ignore\nfn f() {}\n```\n");
+    assert_eq!(code, "fn main() {}\n\nfn f() {}\n");
+    assert!(problems.is_empty());
+
+    let (code, problems) = tangle(Literate::Bird, &[], "Intro\n\n> import Data.List\n\nThen\n\n> main = pure ()\n");
+    assert_eq!(code, "import Data.List\n\nmain = pure ()\n");
+    assert!(problems.is_empty());
 }
 
 #[test]
@@ -70,9 +416,507 @@ fn render_ok() {
     let c = c_language();
     raw.insert("c".to_string(), c);
     let mut langs = Languages::new(raw);
-    if let Some(rendered) = render(&mut langs, "c", C_SAMPLE, &std::path::Path::new("./source_path.c"), "../style.css") {
+    if let Some(rendered) = render(&Highlighting::defaults(), &Markdown::defaults(), &mut langs, "c", C_SAMPLE, &std::path::Path::new("./source_path.c"), "../") {
         println!("file: {:#?}", rendered);
     } else {
         panic!("failed to generate sections");
     }
 }
+
+#[test]
+fn plain_rendering_ok() {
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("c".to_string(), c_language());
+    raw.insert("vhdl".to_string(), "singleline = \"--\"".parse().expect("malformed language"));
+    let mut langs = Languages::new(raw);
+    let highlighting = Highlighting::defaults();
+    assert!(rucco_lib::render::is_highlighted(&highlighting, &mut langs, "c"));
+    assert!(!rucco_lib::render::is_highlighted(&highlighting, &mut langs, "vhdl"));
+    let segments = render_segments(&highlighting, &Markdown::defaults(), &mut langs, "vhdl", "-- counter\nq <= d;\n")
+        .expect("failed to render without syntax");
+    match segments[1] {
+        Segment::Code((ref html, _, _)) => assert!(html.contains("q &lt;= d;"), "{}", html),
+        _ => panic!("expected a code segment")
+    }
+}
+
+#[test]
+fn index_ok() {
+    use std::path::PathBuf;
+    use rucco_lib::templates::{index, Layout, Template};
+    let docfiles: Vec<PathBuf> = ["src/main.rs.html", "src/lib/links.rs.html", "build.rs.html"].iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(index::tree(&docfiles).into_string(),
+               "<ul class=\"tree\"><li class=\"dir\"><span class=\"dir\">src/</span>\
+                <ul class=\"tree\"><li class=\"dir\"><span class=\"dir\">lib/</span>\
+                <ul class=\"tree\"><li class=\"file\"><a class=\"source\" href=\"src/lib/links.rs.html\">links.rs</a></li></ul></li>\
+                <li class=\"file\"><a class=\"source\" href=\"src/main.rs.html\">main.rs</a></li></ul></li>\
+                <li class=\"file\"><a class=\"source\" href=\"build.rs.html\">build.rs</a></li></ul>");
+    for layout in &[Layout::Classic, Layout::Linear, Layout::Parallel] {
+        let html = layout.render_index(&docfiles).expect("failed to render index");
+        assert!(html.contains("<title>Index</title>"), "{}", html);
+        assert!(html.contains("href=\"src/lib/links.rs.html\""), "{}", html);
+    }
+    assert_eq!(Layout::PlainMarkdown.render_index(&docfiles).expect("failed to render index"),
+               "# Index\n\n- src/\n  - lib/\n    - [links.rs](src/lib/links.rs.html)\n  - [main.rs](src/main.rs.html)\n\
+                - [build.rs](build.rs.html)\n");
+    // pages list the others relative to themselves
+    assert_eq!(index::docfile_label(&PathBuf::from("../../src/lib/links.rs.html")), "src/lib/links.rs");
+}
+
+#[test]
+fn linear_ok() {
+    use std::path::{Path, PathBuf};
+    use rucco_lib::templates::{Layout, Page, Template};
+    let segments = vec![Segment::Title((1, "<h1>Links</h1>".to_string())),
+                        Segment::Doc("<p>resolved</p>".to_string()),
+                        Segment::Title((2, "<h2>Wiki</h2>".to_string())),
+                        Segment::Code(("<pre>fn wiki() {}</pre>".to_string(), vec![], vec![3]))];
+    let docfiles = vec![PathBuf::from("../../src/main.rs.html"), PathBuf::from("../../src/lib/links.rs.html")];
+    // normalize.css is found next to style.css, from any depth
+    for &(root, source) in &[("", "links.rs"), ("../../", "src/lib/links.rs")] {
+        let page = Page { root: root, docfiles: &docfiles, source_path: Path::new(source),
+                          language: "rs", segments: &segments,
+                          toc_depth: rucco_lib::outline::DEFAULT_DEPTH, highlight_css: false };
+        let html = Layout::Linear.render_page(&page).expect("failed to render page");
+        assert!(html.contains(&format!("href=\"{}public/stylesheets/normalize.css\"", root)), "{}", html);
+        assert!(html.contains(&format!("href=\"{}style.css\"", root)), "{}", html);
+        // the leading title goes in the header, all segments get their anchor
+        assert!(html.contains("<div class=\"header\"><span id=\"links\"></span><h1>Links</h1>"), "{}", html);
+        assert!(html.contains("<span id=\"segment-1\"></span><p>resolved</p>"), "{}", html);
+        assert!(html.contains("<span id=\"wiki\"></span><h2>Wiki</h2><span id=\"segment-3\"></span><pre>fn wiki() {}</pre>"), "{}", html);
+        for anchor in page.anchors() {
+            assert!(html.contains(&format!(" id=\"{}\"", anchor)), "{} in {}", anchor, html);
+        }
+        assert!(html.contains("<a class=\"source\" href=\"../../src/main.rs.html\">src/main.rs</a>"), "{}", html);
+    }
+
+    // docs before any title, where search results point
+    let segments = vec![Segment::Doc("<p>intro</p>".to_string()),
+                        Segment::Code(("<pre>fn main() {}</pre>".to_string(), vec![], vec![2]))];
+    let page = Page { root: "", docfiles: &[], source_path: Path::new("main.rs"),
+                      language: "rs", segments: &segments,
+                      toc_depth: rucco_lib::outline::DEFAULT_DEPTH, highlight_css: false };
+    let html = Layout::Linear.render_page(&page).expect("failed to render page");
+    assert!(html.contains("<span id=\"segment-0\"></span><p>intro</p>"), "{}", html);
+}
+
+#[test]
+fn parallel_ok() {
+    use std::path::Path;
+    use rucco_lib::templates::{Layout, Page, Template};
+    let segments = vec![Segment::Title((1, "<h1>Parallel</h1>".to_string())),
+                        Segment::Doc("<p>intro</p>".to_string()),
+                        Segment::Code(("<pre>a</pre>".to_string(), vec![], vec![1])),
+                        Segment::Code(("  \n".to_string(), vec![], vec![2])),
+                        Segment::Doc("<p>about b</p>".to_string()),
+                        Segment::Code(("<pre>b</pre>".to_string(), vec![], vec![4])),
+                        Segment::Doc("<p>trailing</p>".to_string())];
+    let page = Page { root: "", docfiles: &[], source_path: Path::new("parallel.rs"),
+                      language: "rs", segments: &segments,
+                      toc_depth: rucco_lib::outline::DEFAULT_DEPTH, highlight_css: false };
+    let html = Layout::Parallel.render_page(&page).expect("failed to render page");
+    // (anchor, has docs, has code): one row per docs and the code following
+    // them, empty code and docs without code having rows of their own
+    let rows: Vec<(&str, bool, bool)> = html.split("<li id=\"").skip(1)
+        .map(|row| (&row[..row.find('"').expect("no row id")],
+                    row.contains("<p>"),
+                    row.contains("<div class=\"content\">")))
+        .collect();
+    assert_eq!(rows, vec![("parallel", true, true), ("segment-3", false, false),
+                          ("segment-4", true, true), ("segment-6", true, false)]);
+    assert!(html.contains("<p>intro</p></div></div><span id=\"segment-2\"></span><div class=\"content\"><pre>a</pre></div>"), "{}", html);
+    assert!(html.contains("<p>about b</p></div></div><span id=\"segment-5\"></span><div class=\"content\"><pre>b</pre></div>"), "{}", html);
+
+    // titles and docs grouped in a row keep their anchors
+    let segments = vec![Segment::Title((1, "<h1>First</h1>".to_string())),
+                        Segment::Doc("<p>intro</p>".to_string()),
+                        Segment::Title((2, "<h2>Second</h2>".to_string())),
+                        Segment::Code(("<pre>a</pre>".to_string(), vec![], vec![1]))];
+    let page = Page { segments: &segments, ..page };
+    let html = Layout::Parallel.render_page(&page).expect("failed to render page");
+    for anchor in page.anchors() {
+        assert!(html.contains(&format!(" id=\"{}\"", anchor)), "{} in {}", anchor, html);
+    }
+    assert!(html.contains("<p>intro</p><span id=\"second\"></span><h2>Second</h2>"), "{}", html);
+}
+
+#[test]
+fn plain_markdown_ok() {
+    use rucco_lib::templates::Page;
+    let raw: toml::Value = r#"
+[rs]
+singleline = "//"
+[md]
+singleline = ""
+literate = true
+literate_suffix = true
+"#.parse().expect("malformed languages");
+    let mut langs = Languages::new(raw.as_table().expect("languages is not a table").clone());
+    let source = "# Main\n\nStarts.\n\n```\nfn main() {}\n```\n";
+    let segments = raw_segments(&mut langs, "rs.md", source).expect("failed to extract literate rust");
+    let language = langs.highlight_extension("rs.md");
+    let page = Page { root: "../", docfiles: &[], source_path: std::path::Path::new("src/main.rs.md"),
+                      language: &language, segments: &segments,
+                      toc_depth: rucco_lib::outline::DEFAULT_DEPTH, highlight_css: false };
+    let markdown = rucco_lib::templates::plain_markdown::render(&page);
+    assert!(markdown.contains("```rs\nfn main() {}\n```"), "{}", markdown);
+}
+
+fn template_dir(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    for &(file, content) in files {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().expect("no template parent dir")).expect("failed to create template dir");
+        std::fs::write(path, content).expect("failed to write template");
+    }
+    dir
+}
+
+#[test]
+fn template_dir_ok() {
+    use rucco_lib::templates::{Page, Template};
+    use rucco_lib::templates::custom::TemplateDir;
+    let dir = template_dir("rucco_template_dir_ok", &[
+        ("base.html", "<html><link href=\"{{ css_path }}\">{% block body %}{% endblock body %}</html>"),
+        ("parts/header.html", "<h1>{{ source_path }}</h1>"),
+        ("macros.html", "{% macro segment(s) %}<div id=\"{{ s.anchor }}\" class=\"{{ s.kind }}\">{{ s.html | safe }}</div>{% endmacro segment %}"),
+        ("page.html", "{% extends \"base.html\" %}{% import \"macros.html\" as macros %}\
+                       {% block body %}{% include \"parts/header.html\" %}\
+                       {% for s in segments %}{{ macros::segment(s=s) }}{% endfor %}\
+                       {% for d in docfiles %}<a href=\"{{ d.href }}\">{{ d.label }}</a>{% endfor %}{% endblock body %}"),
+        ("style.css", "body {}"),
+    ]);
+    let template = TemplateDir::load(&dir).expect("failed to load template dir");
+    assert!(TemplateDir::is_template(std::path::Path::new("parts/header.html")));
+    assert!(!TemplateDir::is_template(std::path::Path::new("style.css")));
+    let segments = vec![Segment::Title((1, "<h1>Main</h1>".to_string())),
+                        Segment::Code(("<pre>fn main() {}</pre>".to_string(), vec![], vec![3]))];
+    let docfiles = vec![std::path::PathBuf::from("../src/lib.rs.html")];
+    let page = Page { root: "../", docfiles: &docfiles, source_path: std::path::Path::new("src/main.rs"),
+                      language: "rs", segments: &segments,
+                      toc_depth: rucco_lib::outline::DEFAULT_DEPTH, highlight_css: false };
+    let html = template.render_page(&page).expect("failed to render page");
+    // tera escapes '/' outside of `| safe`
+    assert_eq!(html, "<html><link href=\"..&#x2F;style.css\"><h1>src&#x2F;main.rs</h1>\
+                      <div id=\"main\" class=\"title\"><h1>Main</h1></div>\
+                      <div id=\"segment-1\" class=\"code\"><pre>fn main() {}</pre></div>\
+                      <a href=\"..&#x2F;src&#x2F;lib.rs.html\">src&#x2F;lib.rs</a></html>");
+    // no index.html: the classic index
+    assert!(template.render_index(&docfiles).is_some());
+
+    let broken = template_dir("rucco_template_dir_broken", &[("page.html", "{% if %}")]);
+    assert!(TemplateDir::load(&broken).is_err());
+    let missing = template_dir("rucco_template_dir_missing", &[("base.html", "<html></html>")]);
+    assert!(TemplateDir::load(&missing).is_err());
+}
+
+#[test]
+fn custom_highlighting_ok() {
+    use std::fs;
+    assert!(Highlighting::load(None, "no such theme", None).is_err());
+
+    let dir = std::env::temp_dir().join("rucco_custom_syntaxes");
+    fs::create_dir_all(&dir).expect("failed to create syntaxes dir");
+    fs::write(dir.join("moo.sublime-syntax"), r#"%YAML 1.2
+---
+name: Moo
+file_extensions: [moo]
+scope: source.moo
+contexts:
+  main:
+    - match: '\bmoo\b'
+      scope: keyword.moo
+"#).expect("failed to write syntax");
+    let highlighting = Highlighting::load(Some(&dir), "InspiredGitHub", None)
+        .expect("failed to load custom syntaxes");
+
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("moo".to_string(), "singleline = \"#\"".parse().expect("malformed language"));
+    raw.insert("cow".to_string(), "singleline = \"#\"\nsyntax = \"Moo\"".parse().expect("malformed language"));
+    let mut langs = Languages::new(raw);
+    assert!(rucco_lib::render::is_highlighted(&highlighting, &mut langs, "moo"));
+    assert!(rucco_lib::render::is_highlighted(&highlighting, &mut langs, "cow"));
+    assert!(!rucco_lib::render::is_highlighted(&Highlighting::defaults(), &mut langs, "moo"));
+}
+
+#[test]
+fn classed_highlighting_ok() {
+    use rucco_lib::highlight::load_theme;
+    let dark_theme = load_theme("base16-ocean.dark", None).expect("failed to load dark theme");
+    let highlighting = Highlighting::load(None, "InspiredGitHub", None).expect("failed to load highlighting")
+        .with_classes(Some(dark_theme));
+    let css = highlighting.css();
+    assert!(css.contains(".code"));
+    assert!(css.contains("@media (prefers-color-scheme: dark)"));
+
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("c".to_string(), c_language());
+    let mut langs = Languages::new(raw);
+    let segments = render_segments(&highlighting, &Markdown::defaults(), &mut langs, "c", "int a = 12;\n")
+        .expect("failed to render");
+    match segments[0] {
+        Segment::Code((ref html, _, _)) => {
+            assert!(html.starts_with("<pre class=\"code\">"), "{}", html);
+            assert!(html.contains("<span class=\"") && !html.contains("style="), "{}", html);
+        },
+        _ => panic!("expected a code segment")
+    }
+}
+
+#[test]
+fn doc_code_highlighting_ok() {
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("c".to_string(), c_language());
+    let mut langs = Languages::new(raw);
+    let src = "// Bumps a:\n//\n// ```\n// a += 1;\n// ```\n//\n// ```python,ignore\n// a = 1\n// ```\n//\n// ```nope\n// a\n// ```\nint a = 12;\n";
+    let segments = render_segments(&Highlighting::defaults(), &Markdown::defaults(), &mut langs, "c", src)
+        .expect("failed to render");
+    match segments[0] {
+        Segment::Doc(ref html) => {
+            assert_eq!(html.matches("<pre style=").count(), 2, "{}", html);
+            assert!(html.contains("<pre><code class=\"language-nope\">a\n</code></pre>"), "{}", html);
+            assert!(!html.contains("language-python"), "{}", html);
+        },
+        _ => panic!("expected a doc segment")
+    }
+}
+
+#[test]
+fn diagrams_ok() {
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("c".to_string(), c_language());
+    let mut langs = Languages::new(raw);
+    let src = "// ```sequence\n// participant S as Server\n// Client -> S: GET\n// S --> Client: 200 OK\n// ```\n//\n// ```dot\n// digraph { a -> b; }\n// ```\n//\n// ```dot\n// digraph { c -> d; }\n// ```\n//\n// ```dot\n// digraph { a -> }\n// ```\n//\n// ```dot\n// digraph {}\n// ```\n//\n// ```sequence\n// Note over A: B\n// ```\nint a = 12;\n";
+    let segments = render_segments(&Highlighting::defaults(), &Markdown::defaults(), &mut langs, "c", src)
+        .expect("failed to render");
+    match segments[0] {
+        Segment::Doc(ref html) => {
+            assert_eq!(html.matches("<div class=\"diagram\"><svg ").count(), 3, "{}", html);
+            assert!(!html.contains("<?xml"), "{}", html);
+            for label in &[">Server</text>", ">Client</text>", ">GET</text>", ">200 OK</text>"] {
+                assert!(html.contains(label), "{} in {}", label, html);
+            }
+            assert!(html.contains("stroke-dasharray=\"4 3\""), "{}", html);
+            for label in &["a", "b", "c", "d"] {
+                assert!(html.contains(&format!(">{}</tspan>", label)), "{} in {}", label, html);
+            }
+            // two graphs on a page, and their markers and arrows not mixed up
+            let mut ids: Vec<&str> = html.split(" id=\"").skip(1)
+                .map(|s| &s[..s.find('"').expect("unterminated id")])
+                .collect();
+            let count = ids.len();
+            assert!(count > 0, "{}", html);
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), count, "{:?}", ids);
+            for url in html.split("url(#").skip(1) {
+                assert!(ids.contains(&&url[..url.find(')').expect("unterminated url")]), "{}", url);
+            }
+            // the broken ones show their source, layout-rs panics included
+            assert_eq!(html.matches("<pre style=").count(), 2, "{}", html);
+            assert!(html.contains("<pre><code class=\"language-sequence\">Note over A: B\n</code></pre>"), "{}", html);
+        },
+        _ => panic!("expected a doc segment")
+    }
+}
+
+#[test]
+fn markdown_corpus_ok() {
+    use std::fs;
+    use std::path::Path;
+    let markdown = Markdown::defaults();
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/markdown");
+    let mut checked = 0;
+    for entry in fs::read_dir(&corpus).expect("no markdown corpus") {
+        let path = entry.expect("failed to read markdown corpus").path();
+        if path.extension().map_or(true, |e| e != "md") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).expect("failed to read markdown");
+        let expected = fs::read_to_string(path.with_extension("html")).expect("no expected html");
+        assert_eq!(markdown.render(&source), expected, "{}", path.display());
+        checked += 1;
+    }
+    assert!(checked > 0);
+
+    let commonmark = Markdown::new(&[]);
+    assert_eq!(commonmark.render("~~a~~ www.x.org\n"), "<p>~~a~~ www.x.org</p>\n");
+}
+
+#[test]
+fn math_ok() {
+    use rucco_lib::markdown::Extension;
+    let markdown = Markdown::new(&[Extension::Math]);
+    let html = markdown.render("Costs $\\frac12 x^2$, not $5 or $6.\n\n$$\\sum_{i=0}^n \\alpha_i$$\n");
+    assert!(html.contains("<math><semantics><mrow><mfrac><mn>1</mn><mn>2</mn></mfrac><msup><mi>x</mi><mn>2</mn></msup></mrow>"), "{}", html);
+    assert!(html.contains("not $5 or $6."), "{}", html);
+    assert!(html.contains("<math display=\"block\">"), "{}", html);
+    assert!(html.contains("<munderover><mo largeop=\"true\" movablelimits=\"true\">∑</mo>"), "{}", html);
+    assert!(html.contains("<msub><mi>α</mi><mi>i</mi></msub>"), "{}", html);
+
+    let html = markdown.render("$\\mathbb{R} \\nope$\n");
+    assert!(html.contains("<mi>ℝ</mi><merror><mtext>\\nope</mtext></merror>"), "{}", html);
+    assert!(html.contains("<annotation encoding=\"application/x-tex\">\\mathbb{R} \\nope</annotation>"), "{}", html);
+
+    let html = markdown.render("$\\left( x$ and $x \\right]$\n");
+    assert!(html.contains("<mrow><merror><mtext>\\left(</mtext></merror><mi>x</mi></mrow>"), "{}", html);
+    assert!(html.contains("<mi>x</mi><merror><mtext>\\right]</mtext></merror>"), "{}", html);
+    assert!(html.contains(">\\left( x</annotation>"), "{}", html);
+
+    // nested too deep
+    let deep = format!("{}x{}", "{".repeat(100), "}".repeat(100));
+    let html = markdown.render(&format!("${}$ and $\\sqrt{}x{}$\n", deep, "{\\sqrt".repeat(100), "}".repeat(100)));
+    assert!(html.contains(&format!("<merror><mtext>{}</mtext></merror>", deep)), "{}", html);
+    assert!(html.contains("<merror><mtext>\\sqrt{\\sqrt{\\sqrt"), "{}", html);
+    let html = markdown.render(&format!("$\\mathbf{}x{}$\n", "{\\mathbf".repeat(100), "}".repeat(100)));
+    assert!(html.contains("<merror><mtext>\\mathbf{\\mathbf{"), "{}", html);
+
+    let html = markdown.render("Costs $5, or $\\frac12 x.\n");
+    assert!(html.contains("Costs $5, or $\\frac12 x."), "{}", html);
+    assert!(!html.contains("<math"), "{}", html);
+    assert!(Markdown::defaults().render("$x$\n").contains("$x$"));
+}
+
+#[test]
+fn symbols_ok() {
+    use std::path::Path;
+    use rucco_lib::links::Links;
+    use rucco_lib::symbols::segment_definitions;
+    let mut rs: toml::Table = BTreeMap::new();
+    rs.insert("singleline".to_string(), toml::Value::String("//[/!]?".to_string()));
+    rs.insert("definitions".to_string(), toml::Value::Array(vec![
+        toml::Value::String(r"(?:pub\s+)?fn\s+(?P<name>\w+)".to_string()),
+        toml::Value::String(r"(?:pub\s+)?struct\s+(?P<name>\w+)".to_string())]));
+    rs.insert("scopes".to_string(), toml::Value::Array(vec![
+        toml::Value::String(r"impl\s+(?P<name>\w+)".to_string())]));
+    let mut raw: toml::Table = BTreeMap::new();
+    raw.insert("rs".to_string(), toml::Value::Table(rs));
+    let mut langs = Languages::new(raw);
+
+    let lib = "/// Languages\npub struct Languages;\n\nimpl Languages {\n    /// gets\n    pub fn get(&self) {\n        fn helper() {}\n    }\n}\n\nfn get() {}\n";
+    let lib_segments = raw_segments(&mut langs, "rs", lib).expect("failed to parse lib");
+    let lib_definitions = segment_definitions(&mut langs, "rs", &lib_segments);
+    let paths: Vec<(String, usize)> = lib_definitions.iter().map(|d| (d.path.join("::"), d.line)).collect();
+    assert_eq!(paths, vec![("Languages".to_string(), 2), ("Languages::get".to_string(), 6),
+                           ("Languages::get::helper".to_string(), 7), ("get".to_string(), 11)]);
+
+    let main = "//! Uses [`Languages::get`] and [`nope`], not [`get`](x)\nfn main() {}\n";
+    let main_segments = raw_segments(&mut langs, "rs", main).expect("failed to parse main");
+    let mut links = Links::new();
+    links.add_page(Path::new("src/main.rs"), Path::new("src/main.rs.md"), &main_segments);
+    links.add_page(Path::new("src/lib.rs"), Path::new("src/lib.rs.md"), &lib_segments);
+    links.add_symbols(Path::new("src/main.rs"), segment_definitions(&mut langs, "rs", &main_segments));
+    links.add_symbols(Path::new("src/lib.rs"), lib_definitions);
+
+    let (resolved, unresolved) = links.resolve_symbols(Path::new("src/main.rs"), "../", &main_segments, false);
+    match resolved[0] {
+        Segment::Doc(ref doc) => assert_eq!(doc, "Uses [`Languages::get`](../src/lib.rs.md) and [`nope`], not [`get`](x)\n"),
+        _ => panic!("expected a doc segment")
+    }
+    assert_eq!(unresolved, vec!["nope".to_string()]);
+
+    let html = vec![Segment::Doc("<p>[<code>get</code>], [<code>crate::Languages.get()</code>], [<code>helper</code>]</p>".to_string())];
+    let (resolved, unresolved) = links.resolve_symbols(Path::new("src/main.rs"), "../", &html, true);
+    match resolved[0] {
+        Segment::Doc(ref doc) => assert_eq!(doc, "<p><a href=\"../src/lib.rs.md#L11\"><code>get</code></a>, \
+                                                  <a href=\"../src/lib.rs.md#L6\"><code>crate::Languages.get()</code></a>, \
+                                                  <a href=\"../src/lib.rs.md#L7\"><code>helper</code></a></p>"),
+        _ => panic!("expected a doc segment")
+    }
+    assert!(unresolved.is_empty());
+
+    // no definitions anywhere: every symbol link is unresolved
+    let (resolved, unresolved) = Links::new().resolve_symbols(Path::new("src/main.rs"), "../", &main_segments, false);
+    match resolved[0] {
+        Segment::Doc(ref doc) => assert_eq!(doc, "Uses [`Languages::get`] and [`nope`], not [`get`](x)\n"),
+        _ => panic!("expected a doc segment")
+    }
+    assert_eq!(unresolved, vec!["Languages::get".to_string(), "nope".to_string()]);
+}
+
+#[test]
+fn search_index_ok() {
+    use rucco_lib::search::SearchIndex;
+    let segments = vec![
+        Segment::Doc("<p>Intro &amp; more</p>".to_string()),
+        Segment::Title((2, "<h2>Conf files</h2>".to_string())),
+        Segment::Doc("<p>parsed <em>once</em></p>".to_string()),
+    ];
+    let mut index = SearchIndex::new();
+    index.add_page(std::path::Path::new("src/main.rs.html"), &segments);
+    assert_eq!(index.to_js(), "var rucco_search_index = {\"pages\":[\"src/main.rs.html\"],\"entries\":\
+                               [[0,\"segment-0\",\"src/main.rs.html\",\"Intro & more\"],\
+                               [0,\"conf-files\",\"Conf files\",\"parsed once\"]]};\n");
+    assert_eq!(index.to_js(), format!("var rucco_search_index = {};\n", index.to_json()));
+
+    // the search box refers to the assets written once next to style.css
+    let search_box = rucco_lib::templates::search::search_box("../").into_string();
+    assert!(search_box.contains("<link rel=\"stylesheet\" media=\"all\" href=\"../search.css\">"), "{}", search_box);
+    assert!(search_box.contains("<script src=\"../search.js\"></script>"), "{}", search_box);
+    assert!(!search_box.contains("<style>"), "{}", search_box);
+}
+
+#[test]
+fn outline_ok() {
+    let segments = vec![
+        Segment::Title((1, "# Rucco".to_string())),
+        Segment::Doc("a docco derivative\n".to_string()),
+        Segment::Title((2, "## Conf files!".to_string())),
+        Segment::Code(("fn parse_conf_file() {}\n".to_string(), vec![], vec![4])),
+        Segment::Title((2, "## Conf files!".to_string())),
+        Segment::Title((4, "#### too deep".to_string())),
+    ];
+    let anchors = rucco_lib::outline::anchors(&segments);
+    assert_eq!(anchors, vec!["rucco", "segment-1", "conf-files", "segment-3", "conf-files-1", "too-deep"]);
+    let outline = rucco_lib::outline::outline(&segments, &anchors, 3);
+    assert_eq!(outline.len(), 1);
+    assert_eq!(outline[0].children.len(), 2);
+    assert_eq!(outline[0].children[1].anchor, "conf-files-1");
+    assert_eq!(rucco_lib::outline::count(&outline), 3);
+
+    // titles looking like positions, before or after them, do not take them
+    let segments = vec![
+        Segment::Title((1, "# Segment 2".to_string())),
+        Segment::Title((2, "## Segment 1".to_string())),
+        Segment::Doc("docs\n".to_string()),
+        Segment::Title((2, "## Segment 2".to_string())),
+    ];
+    let anchors = rucco_lib::outline::anchors(&segments);
+    assert_eq!(anchors, vec!["segment-2-1", "segment-1", "segment-2", "segment-2-2"]);
+}
+
+#[test]
+fn links_ok() {
+    use std::path::Path;
+    let main_segments = vec![Segment::Title((2, "## Conf files".to_string())),
+                             Segment::Code(("fn main() {}\n".to_string(), vec![], vec![3]))];
+    let lib_segments = vec![
+        Segment::Doc("see [[src/main.rs#conf-files]] and [main](../main.rs)\n".to_string()),
+        Segment::Doc("but not [[src/main.rs#nope]] nor [[src/nope.rs|nope]]\n".to_string()),
+        Segment::Doc("## Usage {#usage}\n\n[main](../main.rs#L3), [[src/main.rs#L4]], [usage](#usage)[^1]\n".to_string()),
+    ];
+    let mut links = rucco_lib::links::Links::new();
+    links.add_page(Path::new("src/main.rs"), Path::new("src/main.rs.md"), &main_segments);
+    links.add_page(Path::new("src/lib/lib.rs"), Path::new("src/lib/lib.rs.md"), &lib_segments);
+    let (resolved, broken) = links.resolve(Path::new("src/lib/lib.rs"), "../../", &lib_segments, false);
+    match resolved[0] {
+        Segment::Doc(ref doc) => assert_eq!(doc, "see [src/main.rs#conf-files](../../src/main.rs.md#conf-files) and [main](../../src/main.rs.md)\n"),
+        _ => panic!("expected a doc segment")
+    }
+    match resolved[2] {
+        Segment::Doc(ref doc) => assert!(doc.contains("[main](../../src/main.rs.md#L3)"), "{}", doc),
+        _ => panic!("expected a doc segment")
+    }
+    assert_eq!(broken, vec!["src/main.rs#nope", "src/nope.rs", "src/main.rs#L4"]);
+
+    // rendered ids: footnotes, heading attributes
+    let rendered = vec![Segment::Doc("<h2 id=\"usage\">Usage</h2><p><a href=\"#usage\">usage</a>\
+                                      <sup class=\"footnote-reference\"><a href=\"#1\">1</a></sup></p>\
+                                      <div class=\"footnote-definition\" id=\"1\"></div>".to_string())];
+    links.add_page(Path::new("src/doc.rs"), Path::new("src/doc.rs.html"), &rendered);
+    let (_, broken) = links.resolve(Path::new("src/doc.rs"), "../", &rendered, true);
+    assert!(broken.is_empty(), "{:?}", broken);
+}
@@ -27,8 +27,8 @@ extern crate rayon; /// for parallelism
 extern crate tar;
 extern crate rucco_lib;
 
-use clap::{Arg, ArgMatches, App};
-use std::collections::HashSet;
+use clap::{Arg, ArgMatches, App, SubCommand};
+use std::collections::{BTreeMap, HashSet};
 use std::ops::DerefMut;
 use std::fs::File;
 use std::ffi::OsStr;
@@ -38,11 +38,24 @@ use std::io;
 use std::fs;
 use std::env;
 use std::cell::RefCell;
+use std::panic;
 use walkdir::{WalkDir};
 use tar::Archive;
 use rayon::prelude::*;
 
-use rucco_lib::{Languages, render};
+use rucco_lib::{Highlighting, Languages, Markdown, render_raw_segments, raw_segments};
+use rucco_lib::render::is_highlighted;
+use rucco_lib::diagram;
+use rucco_lib::highlight::{self, load_theme};
+use rucco_lib::markdown::Extension;
+use rucco_lib::languages::Comments;
+use rucco_lib::templates::{self, Layout, Page, Template};
+use rucco_lib::templates::custom::TemplateDir;
+use rucco_lib::segment::RenderedSegment;
+use rucco_lib::search::{self, SearchIndex};
+use rucco_lib::links::Links;
+use rucco_lib::symbols::{segment_definitions, Definition};
+use rucco_lib::tangle::tangle;
 
 // ## Static data
 
@@ -60,6 +73,9 @@ const MAX_DEPTH: u8 = 8;
 /// 256 of those you have a problem...
 const ESTIMATED_MAX_ACTIONS: usize = 256;
 
+/// Only the first line of extensionless files is read, for a shebang.
+const MAX_SHEBANG_LEN: u64 = 256;
+
 /// This will be used for the command line interface.
 const ABOUT: &'static str = "
 Rucco, a docco derivative (documentation generator).
@@ -76,6 +92,8 @@ Command line argument priority > Ruccofile priority > Base config priority.
 struct Args<'a> {
     conf: Option<&'a str>,
     output: Option<&'a str>,
+    template: Option<&'a str>,
+    template_dir: Option<&'a str>,
     nonrecursive: bool,
     inputs: Vec<&'a str>
 }
@@ -83,7 +101,20 @@ struct Args<'a> {
 struct Config<'a> {
     recursive: bool,
     entries: Vec<&'a str>,
+    comments: Comments,
+    annotations: bool,
     output_dir: &'a str,
+    layout: Layout,
+    template_dir: Option<&'a str>,
+    /// the template dir if any, the layout otherwise
+    template: Box<dyn Template>,
+    toc_depth: u8,
+    /// `[highlight]`, for the generated ruccofile
+    highlight: &'a toml::value::Table,
+    highlighting: Highlighting,
+    /// `[markdown]`, for the generated ruccofile
+    markdown_conf: &'a toml::value::Table,
+    markdown: Markdown,
     languages: &'a toml::value::Table
 }
 
@@ -109,6 +140,17 @@ fn cli<'a, 'b>() -> App<'a, 'b> {
              .value_name("TARGETDIR")
              .help("Output directory (default is \"docs\")")
              .takes_value(true))
+        .arg(Arg::with_name("template")
+             .short("t")
+             .long("template")
+             .value_name("TEMPLATE")
+             .help("Page template to use: classic, linear, parallel or plain_markdown (default is \"classic\")")
+             .takes_value(true))
+        .arg(Arg::with_name("template-dir")
+             .long("template-dir")
+             .value_name("TEMPLATEDIR")
+             .help("Directory with a custom page.html template and static assets (overrides --template)")
+             .takes_value(true))
         .arg(Arg::with_name("non-recursive")
              .long("non-recursive")
              .value_name("FILE")
@@ -119,6 +161,20 @@ fn cli<'a, 'b>() -> App<'a, 'b> {
              .multiple(true)
              .value_name("FILES_AND_DIRS")
              .index(1))
+        .subcommand(SubCommand::with_name("tangle")
+                    .about("Writes out the source code of literate files (markdown with code blocks, litcoffee, lhs)")
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("TARGETDIR")
+                         .help("Output directory (default is next to each literate file)")
+                         .takes_value(true))
+                    .arg(Arg::with_name("inputs")
+                         .help("Literate files to tangle")
+                         .multiple(true)
+                         .required(true)
+                         .value_name("FILES")
+                         .index(1)))
 }
 
 /// Given `cli().get-matches() -> ArgMatches`, we choose to create this simple
@@ -130,6 +186,8 @@ impl<'a> Args<'a> {
         Args {
             conf: matches.value_of("config"),
             output: matches.value_of("output"),
+            template: matches.value_of("template"),
+            template_dir: matches.value_of("template-dir"),
             nonrecursive: matches.is_present("non-recursive"),
             inputs: inputs,
         }
@@ -190,18 +248,29 @@ fn ensure_ruccofile_exists(config: &Config) -> Result<(), io::Error> {
         info!("generating configuration file: {}", RUCCOFILE_NAME);
         let mut conf_input: toml::value::Table = toml::map::Map::new();
         let mut conf_output: toml::value::Table = toml::map::Map::new();
+        let mut conf_highlight: toml::value::Table = toml::map::Map::new();
+        let mut conf_markdown: toml::value::Table = toml::map::Map::new();
         let mut conf_languages: toml::value::Table = toml::map::Map::new();
         let mut input: toml::value::Table = toml::map::Map::new();
         let mut output: toml::value::Table = toml::map::Map::new();
 
         input.insert("recursive".to_string(), toml::Value::Boolean(config.recursive));
+        input.insert("comments".to_string(), toml::Value::String(config.comments.name().to_string()));
+        input.insert("annotations".to_string(), toml::Value::Boolean(config.annotations));
         output.insert("dir".to_string(), toml::Value::String(config.output_dir.to_string()));
+        output.insert("template".to_string(), toml::Value::String(config.layout.name().to_string()));
+        output.insert("toc_depth".to_string(), toml::Value::Integer(config.toc_depth as i64));
+        if let Some(template_dir) = config.template_dir {
+            output.insert("template_dir".to_string(), toml::Value::String(template_dir.to_string()));
+        }
         input.insert("entries".to_string(), toml::Value::Array(
             config.entries.iter().map(|v| toml::Value::String(v.to_string())).collect()
         ));
 
         conf_input.insert("input".to_string(), toml::Value::Table(input));
         conf_output.insert("output".to_string(), toml::Value::Table(output));
+        conf_highlight.insert("highlight".to_string(), toml::Value::Table(config.highlight.clone()));
+        conf_markdown.insert("markdown".to_string(), toml::Value::Table(config.markdown_conf.clone()));
         conf_languages.insert("languages".to_string(), toml::Value::Table(config.languages.clone()));
 
         let mut ruccofile = File::create(RUCCOFILE_NAME)?;
@@ -210,6 +279,10 @@ fn ensure_ruccofile_exists(config: &Config) -> Result<(), io::Error> {
         ruccofile.write_all("\n".as_bytes())?;
         ruccofile.write_all(toml::to_string(&conf_output).unwrap().as_bytes())?;
         ruccofile.write_all("\n".as_bytes())?;
+        ruccofile.write_all(toml::to_string(&conf_highlight).unwrap().as_bytes())?;
+        ruccofile.write_all("\n".as_bytes())?;
+        ruccofile.write_all(toml::to_string(&conf_markdown).unwrap().as_bytes())?;
+        ruccofile.write_all("\n".as_bytes())?;
         ruccofile.write_all(toml::to_string(&conf_languages).unwrap().as_bytes())?;
     }
     Ok(())
@@ -233,45 +306,190 @@ fn untar_resources(output_dir: &Path,
     Ok(())
 }
 
+/// Everything in a template dir but the templates themselves is copied as-is.
+fn copy_template_assets(template_dir: &Path, output_dir: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(template_dir)
+        .follow_links(false)
+        .max_depth(MAX_DEPTH as usize)
+        .into_iter()
+        .filter_map(|p| p.ok())
+    {
+        let relative = entry.path().strip_prefix(template_dir)
+            .expect("failed to generate a relative path.");
+        if entry.path().is_dir() {
+            ensure_dir(&output_dir.join(relative))?;
+        } else if !TemplateDir::is_template(relative) {
+            fs::copy(entry.path(), output_dir.join(relative))?;
+        }
+    }
+    Ok(())
+}
+
 thread_local! {
     static LANG: RefCell<Option<Languages>> = RefCell::new(None);
 }
 
-fn process_file(config: &Config, source: &Path, target: &Path) -> io::Result<()> {
+/// First pass over a file: segments are extracted and rendered, but not put in
+/// a page yet (we need to know which files could be rendered for that), and
+/// its definitions are collected for the symbol links of every page. Also
+/// returns the highlight extension of its code.
+fn process_file(config: &Config, source: &Path) -> io::Result<Option<(String, Vec<RenderedSegment>, Vec<Definition>)>> {
     LANG.with(|l| {
         let needs_init = l.borrow().is_none();
         if needs_init {
             debug!("thread local languages struct init.");
-            *l.borrow_mut() = Some(Languages::new(config.languages.clone()));
+            *l.borrow_mut() = Some(Languages::new(config.languages.clone())
+                                   .with_comments(config.comments)
+                                   .with_annotations(config.annotations));
         }
         if let &mut Some(ref mut languages) = l.borrow_mut().deref_mut() {
-            // source path is relative to current dir, so it's depth gives us
-            // how many times the path to css. "../../ depth times /style.css"
-            let mut css_path = String::new();
-            for _ in source.components().skip(1) {
-                css_path.push_str("../");
-            }
-            css_path.push_str("style.css");
-            if let Some(extension) = source.extension().and_then(&OsStr::to_str) {
+            if let Some(extension) = detect_language(languages, source) {
                 let mut source_text = String::new();
                 File::open(source)?.read_to_string(&mut source_text)?;
-                if let Some(ref rendered) = render(languages, extension, source_text.as_str(), source, css_path.as_str()) {
-                    File::create(target)?.write_all(rendered.as_bytes())?;
-                    info!("rendered {} to {}", source.display(), target.display());
+                let segments = match raw_segments(languages, &extension, source_text.as_str()) {
+                    Some(segments) => segments,
+                    None => {
+                        warn!("failed to render {}!", source.display());
+                        return Ok(None);
+                    }
+                };
+                let definitions = segment_definitions(languages, &extension, &segments);
+                let segments = if config.template.is_html() {
+                    render_raw_segments(&config.highlighting, &config.markdown, languages, &extension, segments)
                 } else {
-                    warn!("failed to render {}!", source.display());
-                }
+                    segments
+                };
+                let language = languages.highlight_extension(&extension);
+                return Ok(Some((language, segments, definitions)));
             } else {
                 debug!("skipping {}", source.display());
             }
         }
-        Ok(())
+        Ok(None)
     })
 }
 
-fn htmlize(mut p: PathBuf) -> PathBuf {
+/// The language of a file, from its path or else from its shebang.
+fn detect_language(languages: &Languages, source: &Path) -> Option<String> {
+    languages.language_of(source, None).or_else(|| {
+        let mut first_line = vec![];
+        io::BufReader::new(File::open(source).ok()?)
+            .take(MAX_SHEBANG_LEN)
+            .read_until(b'\n', &mut first_line).ok()?;
+        languages.language_of(source, Some(&String::from_utf8_lossy(&first_line)))
+    })
+}
+
+/// Lists the languages whose code was rendered as plain text, sublime having
+/// no syntax for them.
+fn report_unhighlighted(config: &Config, rendered: &[(&PathBuf, &PathBuf, Vec<RenderedSegment>)]) {
+    let mut languages = Languages::new(config.languages.clone());
+    let mut unhighlighted: BTreeMap<String, usize> = BTreeMap::new();
+    for &(source, _, _) in rendered {
+        if let Some(language) = detect_language(&languages, source) {
+            if !is_highlighted(&config.highlighting, &mut languages, &language) {
+                *unhighlighted.entry(language).or_insert(0) += 1;
+            }
+        }
+    }
+    if !unhighlighted.is_empty() {
+        let summary: Vec<String> = unhighlighted.iter()
+            .map(|(language, &count)| format!("{} ({} file{})", language, count, if count > 1 { "s" } else { "" }))
+            .collect();
+        warn!("no syntax highlighting for: {} (a substitute can be set with the highlight or syntax keys)",
+              summary.join(", "));
+    }
+}
+
+/// source path is relative to current dir, so it's depth gives us
+/// how many times we need to go up to reach the output dir root.
+/// "../" depth times.
+fn relative_root(source: &Path) -> String {
+    let mut root = String::new();
+    for _ in source.components().skip(1) {
+        root.push_str("../");
+    }
+    root
+}
+
+/// Second pass: the page is put together, with links to all the other pages.
+fn write_page(config: &Config, source: &Path, target: &Path, language: &str,
+              segments: &[RenderedSegment], docfiles: &[PathBuf], links: &Links) -> io::Result<()> {
+    let root = relative_root(source);
+    let (segments, broken) = links.resolve(source, &root, segments, config.template.is_html());
+    for link in broken {
+        warn!("broken link in {}: {}", source.display(), link);
+    }
+    let (segments, unresolved) = links.resolve_symbols(source, &root, &segments, config.template.is_html());
+    for symbol in unresolved {
+        warn!("unresolved symbol link in {}: {}", source.display(), symbol);
+    }
+    let page_docfiles: Vec<PathBuf> = docfiles.iter()
+        .map(|d| Path::new(&root).join(d))
+        .collect();
+    let page = Page { root: &root, docfiles: &page_docfiles,
+                      source_path: source, language: language, segments: &segments,
+                      toc_depth: config.toc_depth,
+                      highlight_css: config.highlighting.classes };
+    if let Some(rendered) = config.template.render_page(&page) {
+        File::create(target)?.write_all(rendered.as_bytes())?;
+        info!("rendered {} to {}", source.display(), target.display());
+    } else {
+        warn!("failed to render page for {}!", source.display());
+    }
+    Ok(())
+}
+
+fn write_index(config: &Config, output_dir: &Path, docfiles: &[PathBuf]) -> io::Result<()> {
+    let target = docfile_path(output_dir.join("index"), config.template.extension());
+    if let Some(rendered) = config.template.render_index(docfiles) {
+        File::create(&target)?.write_all(rendered.as_bytes())?;
+        info!("rendered index to {}", target.display());
+    } else {
+        warn!("failed to render index!");
+    }
+    Ok(())
+}
+
+/// Titles and docs of every page, for the search box of the html templates.
+fn write_search_index(output_dir: &Path, docfiles: &[PathBuf],
+                      rendered: &[(&PathBuf, &PathBuf, Vec<RenderedSegment>)]) -> io::Result<()> {
+    let mut index = SearchIndex::new();
+    for (docfile, &(_, _, ref segments)) in docfiles.iter().zip(rendered.iter()) {
+        index.add_page(docfile, segments);
+    }
+    let target = output_dir.join(search::INDEX_FILE);
+    File::create(&target)?.write_all(index.to_json().as_bytes())?;
+    info!("wrote search index to {}", target.display());
+    let target = output_dir.join(search::INDEX_SCRIPT_FILE);
+    File::create(&target)?.write_all(index.to_js().as_bytes())?;
+    info!("wrote search index script to {}", target.display());
+    Ok(())
+}
+
+/// The script and style of the search box, shared by every page.
+fn write_search_assets(output_dir: &Path) -> io::Result<()> {
+    for &(file, content) in &[(templates::search::SCRIPT_FILE, templates::search::SCRIPT),
+                              (templates::search::CSS_FILE, templates::search::CSS)] {
+        let target = output_dir.join(file);
+        File::create(&target)?.write_all(content.as_bytes())?;
+        info!("wrote search asset to {}", target.display());
+    }
+    Ok(())
+}
+
+/// Styles the classes of the highlighted code, after the configured themes.
+fn write_highlight_css(config: &Config, output_dir: &Path) -> io::Result<()> {
+    let target = output_dir.join(highlight::CSS_FILE);
+    File::create(&target)?.write_all(config.highlighting.css().as_bytes())?;
+    info!("wrote highlight stylesheet to {}", target.display());
+    Ok(())
+}
+
+/// "main.rs" -> "main.rs.html" (or "main.rs.md"...)
+fn docfile_path(mut p: PathBuf, extension: &str) -> PathBuf {
     let new_f = if let Some(f) = p.file_name() {
-        Some([f.to_str().expect("invalid path"), ".html"].concat())
+        Some([f.to_str().expect("invalid path"), ".", extension].concat())
     } else {
         None
     };
@@ -281,11 +499,76 @@ fn htmlize(mut p: PathBuf) -> PathBuf {
     p
 }
 
+// ## Tangling
+
+/// "design.rs.md" -> "design.rs", "intro.litcoffee" -> "intro.coffee"
+fn tangled_path(source: &Path, extension: &str) -> PathBuf {
+    let stem = source.file_stem().and_then(OsStr::to_str).expect("invalid path");
+    if Path::new(stem).extension().and_then(OsStr::to_str) == Some(extension) {
+        source.with_file_name(stem)
+    } else {
+        source.with_file_name([stem, ".", extension].concat())
+    }
+}
+
+/// Writes out the code of a literate file, next to it or in `output_dir`.
+fn tangle_file(languages: &mut Languages, source: &Path, output_dir: Option<&str>) -> io::Result<()> {
+    let extension = match languages.language_of(source, None) {
+        Some(extension) => extension,
+        None => {
+            warn!("skipping {}: unknown language", source.display());
+            return Ok(());
+        }
+    };
+    let literate = match languages.get(&extension) {
+        &Some(ref syntax) => syntax.literate.map(|literate| (literate, syntax.fence_tags.clone())),
+        &None => None
+    };
+    let (literate, tags) = match literate {
+        Some(literate) => literate,
+        None => {
+            warn!("skipping {}: not a literate file", source.display());
+            return Ok(());
+        }
+    };
+
+    let mut source_text = String::new();
+    File::open(source)?.read_to_string(&mut source_text)?;
+    let (code, problems) = tangle(literate, &tags, &source_text);
+    for problem in problems {
+        warn!("{} in {}", problem, source.display());
+    }
+
+    let tangled = tangled_path(source, &languages.highlight_extension(&extension));
+    let target = match output_dir {
+        Some(dir) => Path::new(dir).join(tangled),
+        None => tangled
+    };
+    if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        ensure_dir(&parent.to_owned())?;
+    }
+    File::create(&target)?.write_all(code.as_bytes())?;
+    info!("tangled {} to {}", source.display(), target.display());
+    Ok(())
+}
+
 // ## The main function!
 
+/// Graphs layout-rs cannot lay out make it panic: the panics are caught and
+/// the diagrams shown as code with a warning, there is no need to print them.
+fn quiet_diagram_panics() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !diagram::is_laying_out() {
+            default_hook(info);
+        }
+    }));
+}
+
 /// And now we put everything together.
 fn main() {
     env_logger::init();
+    quiet_diagram_panics();
 
     let matches = cli().get_matches();
     let args = Args::new(&matches);
@@ -300,6 +583,21 @@ fn main() {
     });
     let conf = merge_tables(&base_conf, &custom_conf);
 
+    if let Some(tangle_matches) = matches.subcommand_matches("tangle") {
+        debug!("# TANGLE");
+        let languages = conf.get("languages").expect("malformed conf - no languages")
+            .as_table().expect("malformed conf - languages is not a table");
+        let mut languages = Languages::new(languages.clone());
+        let output_dir = tangle_matches.value_of("output");
+        for input in tangle_matches.values_of("inputs").into_iter().flat_map(|v| v) {
+            tangle_file(&mut languages, Path::new(input), output_dir).unwrap_or_else(|e| {
+                error!("failed to tangle {}: {}", input, e);
+            });
+        }
+        info!("complete!");
+        return;
+    }
+
     let conf_input = conf.get("input").expect("malformed conf - no input")
         .as_table().expect("malformed conf - input is not a table");
     let conf_output = conf.get("output").expect("malformed conf - no output")
@@ -314,6 +612,32 @@ fn main() {
             .as_str().expect("malformed conf - output.dir is not a string")
     };
 
+    // template
+    debug!("# TEMPLATE");
+    let template = if let Some(template) = args.template {
+        template
+    } else {
+        conf_output.get("template").expect("malformed conf - no output.template")
+            .as_str().expect("malformed conf - output.template is not a string")
+    };
+    let layout = Layout::from_name(template)
+        .unwrap_or_else(|| panic!("unknown template: {}", template));
+    let template_dir = args.template_dir.or_else(|| {
+        conf_output.get("template_dir")
+            .map(|v| v.as_str().expect("malformed conf - output.template_dir is not a string"))
+    });
+    let template: Box<dyn Template> = if let Some(dir) = template_dir {
+        Box::new(TemplateDir::load(Path::new(dir)).unwrap_or_else(|e| {
+            panic!("failed to load template dir {}: {}", dir, e);
+        }))
+    } else {
+        Box::new(layout)
+    };
+
+    // table of contents
+    let toc_depth = conf_output.get("toc_depth").expect("malformed conf - no output.toc_depth")
+        .as_integer().expect("malformed conf - output.toc_depth is not an integer") as u8;
+
     // nonrecursive
     /// using ! and || makes it hard to read, so ifs!
     let recursive = if args.nonrecursive {
@@ -324,6 +648,16 @@ fn main() {
             .as_bool().expect("malformed conf - input.recursive is not a boolean")
     };
 
+    // comments
+    let comments = conf_input.get("comments").expect("malformed conf - no input.comments")
+        .as_str().expect("malformed conf - input.comments is not a string");
+    let comments = Comments::from_name(comments)
+        .unwrap_or_else(|| panic!("unknown comments mode: {} (all or doc-only)", comments));
+
+    // annotations
+    let annotations = conf_input.get("annotations").expect("malformed conf - no input.annotations")
+        .as_bool().expect("malformed conf - input.annotations is not a boolean");
+
     // inputs
     debug!("# INPUTS");
     let entries = if args.inputs.is_empty() {
@@ -336,13 +670,62 @@ fn main() {
         args.inputs
     };
 
+    // highlighting
+    debug!("# HIGHLIGHT");
+    let conf_highlight = conf.get("highlight").expect("malformed conf - no highlight")
+        .as_table().expect("malformed conf - highlight is not a table");
+    let highlight_path = |key: &str| conf_highlight.get(key).map(|v| {
+        Path::new(v.as_str().unwrap_or_else(|| panic!("malformed conf - highlight.{} is not a string", key)))
+    });
+    let theme = conf_highlight.get("theme").expect("malformed conf - no highlight.theme")
+        .as_str().expect("malformed conf - highlight.theme is not a string");
+    let highlighting = Highlighting::load(highlight_path("syntaxes_dir"), theme, highlight_path("theme_file"))
+        .unwrap_or_else(|e| panic!("failed to load highlighting: {}", e));
+    let line_numbers = conf_highlight.get("line_numbers").expect("malformed conf - no highlight.line_numbers")
+        .as_bool().expect("malformed conf - highlight.line_numbers is not a boolean");
+    let highlighting = highlighting.with_line_numbers(line_numbers);
+    let classes = conf_highlight.get("classes").expect("malformed conf - no highlight.classes")
+        .as_bool().expect("malformed conf - highlight.classes is not a boolean");
+    let highlighting = if classes {
+        let dark_theme = conf_highlight.get("dark_theme")
+            .map(|v| v.as_str().expect("malformed conf - highlight.dark_theme is not a string"));
+        let dark_theme_file = highlight_path("dark_theme_file");
+        let dark_theme = if dark_theme.is_some() || dark_theme_file.is_some() {
+            Some(load_theme(dark_theme.unwrap_or(""), dark_theme_file)
+                 .unwrap_or_else(|e| panic!("failed to load dark theme: {}", e)))
+        } else {
+            None
+        };
+        highlighting.with_classes(dark_theme)
+    } else {
+        highlighting
+    };
+
+    // markdown
+    debug!("# MARKDOWN");
+    let conf_markdown = conf.get("markdown").expect("malformed conf - no markdown")
+        .as_table().expect("malformed conf - markdown is not a table");
+    let extensions: Vec<Extension> = conf_markdown.get("extensions").expect("malformed conf - no markdown.extensions")
+        .as_array().expect("malformed conf - markdown.extensions is not an array")
+        .iter().map(|v| {
+            let name = v.as_str().expect("malformed conf - one of markdown.extensions is not a string");
+            Extension::from_name(name).unwrap_or_else(|| panic!("unknown markdown extension: {}", name))
+        })
+        .collect();
+    let markdown = Markdown::new(&extensions);
+
     // languages
     debug!("# LANGUAGES");
     let languages = conf.get("languages").expect("malformed conf - no languages")
         .as_table().expect("malformed conf - languages is not a table");
 
-    let config = Config { recursive: recursive, entries: entries, output_dir: output_dir,
-                          languages: &languages };
+    let config = Config { recursive: recursive, entries: entries, comments: comments,
+                          annotations: annotations,
+                          output_dir: output_dir,
+                          layout: layout, template_dir: template_dir, template: template,
+                          toc_depth: toc_depth, highlight: conf_highlight,
+                          highlighting: highlighting, markdown_conf: conf_markdown,
+                          markdown: markdown, languages: &languages };
 
     // if a ruccofile was not given as parameter, ensure a local one exists (create if necessary).
     debug!("# RUCCOFILE");
@@ -386,7 +769,7 @@ fn main() {
                 } else {
                     let target = output_dir.join(&relative);
                     debug!("+ file: {}", relative.display());
-                    files.push((relative.to_owned(), htmlize(target)))
+                    files.push((relative.to_owned(), docfile_path(target, config.template.extension())))
                 }
             }
         }
@@ -400,7 +783,7 @@ fn main() {
                 .expect("failed to generate a relative path.");
             let target = output_dir.join(&relative);
             debug!("+ file: {}", relative.display());
-            files.push((relative.to_owned(), htmlize(target)));
+            files.push((relative.to_owned(), docfile_path(target, config.template.extension())));
         }
     }
 
@@ -412,16 +795,85 @@ fn main() {
     }
 
     debug!("## Processing files");
-    let mut res: Vec<io::Result<()>> = vec![];
+    let mut processed: Vec<io::Result<Option<(String, Vec<RenderedSegment>, Vec<Definition>)>>> = vec![];
     files.par_iter()
-        .map(|&(ref source, ref target)|
-             process_file(&config, source, target))
-        .collect_into_vec(&mut res);
+        .map(|&(ref source, _)| process_file(&config, source))
+        .collect_into_vec(&mut processed);
+    // of the rendered files, in the same order
+    let mut page_languages: Vec<String> = vec![];
+    let mut definitions: Vec<Vec<Definition>> = vec![];
+    let rendered: Vec<(&PathBuf, &PathBuf, Vec<RenderedSegment>)> = files.iter()
+        .zip(processed.into_iter())
+        .filter_map(|(&(ref source, ref target), res)| match res {
+            Ok(Some((language, segments, defined))) => {
+                page_languages.push(language);
+                definitions.push(defined);
+                Some((source, target, segments))
+            },
+            Ok(None) => None,
+            Err(e) => {
+                error!("failed to process {}: {}", source.display(), e);
+                None
+            }
+        })
+        .collect();
 
-    debug!("## Untar resources");
-    untar_resources(&output_dir, "classic.tar").unwrap_or_else(|e| {
-        panic!("resource extraction failed: {:?}", e);
+    if config.template.is_html() {
+        report_unhighlighted(&config, &rendered);
+    }
+
+    debug!("## Writing pages");
+    // paths of the generated pages, relative to the output dir
+    let docfiles: Vec<PathBuf> = rendered.iter()
+        .map(|&(source, _, _)| docfile_path(source.clone(), config.template.extension()))
+        .collect();
+    let mut links = Links::new();
+    for (docfile, &(source, _, ref segments)) in docfiles.iter().zip(rendered.iter()) {
+        links.add_page(source, docfile, segments);
+    }
+    for (&(source, _, _), defined) in rendered.iter().zip(definitions.into_iter()) {
+        links.add_symbols(source, defined);
+    }
+    let mut res: Vec<io::Result<()>> = vec![];
+    rendered.par_iter()
+        .zip(page_languages.par_iter())
+        .map(|(&(source, target, ref segments), language)|
+             write_page(&config, source, target, language, segments, &docfiles, &links))
+        .collect_into_vec(&mut res);
+    for e in res.into_iter().filter_map(Result::err) {
+        error!("failed to write page: {}", e);
+    }
+    write_index(&config, &output_dir, &docfiles).unwrap_or_else(|e| {
+        error!("failed to write index: {}", e);
     });
+
+    if config.template.is_html() {
+        debug!("## Writing search index");
+        write_search_index(&output_dir, &docfiles, &rendered).unwrap_or_else(|e| {
+            error!("failed to write search index: {}", e);
+        });
+        write_search_assets(&output_dir).unwrap_or_else(|e| {
+            error!("failed to write search assets: {}", e);
+        });
+        if config.highlighting.classes {
+            debug!("## Writing highlight stylesheet");
+            write_highlight_css(&config, &output_dir).unwrap_or_else(|e| {
+                error!("failed to write highlight stylesheet: {}", e);
+            });
+        }
+    }
+
+    if let Some(template_dir) = config.template_dir {
+        debug!("## Copy template assets");
+        copy_template_assets(Path::new(template_dir), &output_dir).unwrap_or_else(|e| {
+            panic!("template assets copy failed: {:?}", e);
+        });
+    } else if let Some(pack_name) = config.layout.resources() {
+        debug!("## Untar resources");
+        untar_resources(&output_dir, pack_name).unwrap_or_else(|e| {
+            panic!("resource extraction failed: {:?}", e);
+        });
+    }
     ;
     info!("complete!");
 }